@@ -0,0 +1,19 @@
+;-------------------------------------;
+; a long running loop for rustrone bench ;
+;-------------------------------------;
+put r2 0; to compare with
+put r3 100; outer loop counter
+
+@outer;
+    put r0 0; wraps around, so the inner loop runs 65536 times
+
+@inner;
+    dec r0;
+    cmp r0 r2;
+    jne @inner;
+
+    dec r3;
+    cmp r3 r2;
+    jne @outer;
+
+halt;
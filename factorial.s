@@ -16,4 +16,13 @@ put r3 1; to compare with decreasing r0
     cmp r0 r3;
     jne @repeat;
 
+; print the result, the high byte first
+put r2 16;
+mul r2 r2; 256
+div r1 r2; r0 = high byte, r1 = low byte
+out 1 r0;
+out 2 r1;
+put r0 10; newline
+out 0 r0;
+
 halt;
@@ -1,5 +1,8 @@
 mod full_instruction;
 
+mod symbols;
+pub use symbols::{SourceLine, Symbols};
+
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
@@ -7,10 +10,12 @@ use std::path::Path;
 
 use self::full_instruction::FullInstruction;
 
+#[derive(Default)]
 pub struct Assembler {
     assembled: Vec<u8>,
     labels: HashMap<String, usize>,
     instrs: Vec<FullInstruction>,
+    origin: usize, // the address the code is loaded at
 }
 
 impl Assembler {
@@ -19,44 +24,61 @@ impl Assembler {
             assembled: Vec::new(),
             labels: HashMap::new(),
             instrs: Vec::new(),
+            origin: 0,
         }
     }
 
-    pub fn assemble(mut self, filename: &str) -> Vec<u8> {
+    pub fn with_origin(origin: u16) -> Self {
+        Self { origin: origin as usize, ..Self::new() }
+    }
+
+    pub fn assemble(self, filename: &str) -> Result<Vec<u8>, String> {
+        Ok(self.assemble_with_symbols(filename)?.0)
+    }
+
+    // Also returns where every label and source line ended up.
+    // Errors name the file and line they're on
+    pub fn assemble_with_symbols(mut self, filename: &str) -> Result<(Vec<u8>, Symbols), String> {
         self.assembled = Vec::<u8>::new();
+        let mut symbols = Symbols::default();
 
         let path = Path::new(&filename);
 
         let file = match File::open(path) {
-            Err(why) => panic!("couldn't open {}: {}", path.display(), why),
+            Err(why) => return Err(format!("couldn't open {}: {}", path.display(), why)),
             Ok(file) => file,
         };
 
         let lines = io::BufReader::new(file).lines();
-        let mut current_byte: usize = 0;
-        for line in lines {
+        let mut current_byte = self.origin;
+        for (idx, line) in lines.enumerate() {
             if let Ok(line) = line {
-                let instr = FullInstruction::new(&line);
+                let instr = FullInstruction::new(&line).map_err(|e| format!("{}:{}: {}", filename, idx + 1, e))?;
 
                 match instr.as_label() {
                     Some(s) => {
                         self.labels.insert(s, current_byte);
                     },
-                    None => {
+                    None if instr.size() > 0 => {
+                        let source = SourceLine { file: filename.to_string(), line: idx + 1, text: line.trim().to_string() };
+                        symbols.lines.insert(current_byte as u16, source);
                         current_byte += instr.size()
                     },
+                    None => {},
                 }
 
                 self.instrs.push(instr);
             }
         }
 
-        self.assembled.reserve(current_byte);
-        for mut instr in self.instrs {
-            self.assembled.append(&mut instr.build(& self.labels));
+        self.assembled.reserve(current_byte - self.origin);
+        // There's an instruction for every line that could be read
+        for (idx, mut instr) in self.instrs.into_iter().enumerate() {
+            let mut bytes = instr.build(& self.labels).map_err(|e| format!("{}:{}: {}", filename, idx + 1, e))?;
+            self.assembled.append(&mut bytes);
         }
 
-        self.labels.clear();
-        return self.assembled;
+        symbols.labels = self.labels.drain().map(|(label, addr)| (label, addr as u16)).collect();
+        Ok((self.assembled, symbols))
     }
 }
@@ -1,9 +1,9 @@
 use std::collections::HashMap;
 
-use crate::instructions::{operands::OperandType, instr_from_str, Executable, get_instruction_size};
+use crate::instructions::{operands::OperandType, find_instruction, is_mnemonic, InstructionSpec};
 
 pub enum InstructionWord {
-    Instruction(&'static Box<dyn Executable + Sync + 'static>),
+    Instruction(&'static InstructionSpec),
     Label(String),
     Data(u8),
     None,
@@ -12,7 +12,6 @@ pub enum InstructionWord {
 pub struct FullInstruction {
     pub instruction: InstructionWord,
     operands: Vec<String>,
-    operand_types: Vec<OperandType>,
     size: usize,
 }
 
@@ -22,8 +21,7 @@ impl FullInstruction {
         let mut start_idx = 0_usize;
         let mut end_idx: usize;
 
-        let mut words: Vec<String> = Vec::new();
-        words.reserve(3);
+        let mut words: Vec<String> = Vec::with_capacity(3);
 
         for ch in line.chars().enumerate() {
             end_idx = ch.0 + 1;
@@ -46,29 +44,17 @@ impl FullInstruction {
         words
     }
 
-    pub fn new(line: &str) -> Self {
+    pub fn new(line: &str) -> Result<Self, String> {
         let words = Self::get_words(line);
 
-        if words.len() == 0 { // blank line or comment
-            return Self {
+        if words.is_empty() { // blank line or comment
+            return Ok(Self {
                 instruction: InstructionWord::None,
                 operands: Vec::new(),
-                operand_types: Vec::new(),
                 size: 0,
-            }
+            })
         }
 
-        let instruction = match instr_from_str(&words[0]) {
-            Some(instr) => InstructionWord::Instruction(instr),
-            None => if words[0].starts_with('@') { // a label
-                InstructionWord::Label(words[0].clone())
-            } else if words[0].starts_with('#') { // raw data
-                InstructionWord::Data(words[0][1..].parse().expect("Raw data should be a u8 value!"))
-            } else {
-                InstructionWord::None
-            },
-        };
-
         let operands = if words.len() > 1 {
             words[1..].to_vec()
         } else {
@@ -85,20 +71,32 @@ impl FullInstruction {
             }
         }
 
+        let instruction = match find_instruction(&words[0], &operand_types) {
+            Some(instr) => InstructionWord::Instruction(instr),
+            None => if is_mnemonic(&words[0]) {
+                return Err(format!("{} doesn't take operands of types {:?}", words[0], operand_types));
+            } else if words[0].starts_with('@') { // a label
+                InstructionWord::Label(words[0].clone())
+            } else if words[0].starts_with('#') { // raw data
+                InstructionWord::Data(words[0][1..].parse().map_err(|_| format!("raw data {} should be a u8 value", words[0]))?)
+            } else {
+                InstructionWord::None
+            },
+        };
+
         // Calculate total size in bytes
 
-        let size: usize = if let InstructionWord::Instruction(_instr) = instruction {
-            get_instruction_size(& operand_types) as usize
-        } else {
-            0usize
+        let size: usize = match instruction {
+            InstructionWord::Instruction(instr) => instr.size(),
+            InstructionWord::Data(_) => 1,
+            _ => 0,
         };
 
-        Self {
+        Ok(Self {
             instruction,
             operands,
-            operand_types,
             size,
-        }
+        })
     }
 
     pub fn size(&self) -> usize { self.size }
@@ -110,15 +108,19 @@ impl FullInstruction {
         }
     }
 
-    pub fn build(&mut self, labels: &HashMap<String, usize>) -> Vec<u8> {
+    pub fn build(&mut self, labels: &HashMap<String, usize>) -> Result<Vec<u8>, String> {
         if let InstructionWord::Data(data) = self.instruction {
-            return vec![data]
+            return Ok(vec![data])
         }
 
-        // TODO: compile labels
+        // Labels are only usable as one byte values
         for op in &mut self.operands {
             if op.starts_with('@') { // It is a label
-                *op = labels.get(op).expect("There is no such label!").to_string();
+                let addr = *labels.get(op).ok_or_else(|| format!("there is no label {}", op))?;
+                if addr > u8::MAX as usize {
+                    return Err(format!("label {} is at address {}, out of reach of a one byte value", op, addr));
+                }
+                *op = addr.to_string();
             }
         }
 
@@ -126,9 +128,9 @@ impl FullInstruction {
 
         if let InstructionWord::Instruction(instr) = & self.instruction {
             ret.reserve_exact(self.size);
-            ret = instr.assemble(& self.operands, & self.operand_types);
+            ret = instr.assemble(& self.operands)?;
         }
 
-        ret
+        Ok(ret)
     }
 }
@@ -0,0 +1,65 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::instructions::MAX_INSTRUCTION_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: usize, // starting at 1
+    pub text: String,
+}
+
+/// Where the labels and instructions of assembled programs ended up.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    pub labels: HashMap<String, u16>, // with the leading @
+    pub lines: BTreeMap<u16, SourceLine>, // by the address of the instruction or data byte
+}
+
+impl Symbols {
+    // Programs loaded later win for addresses they share
+    pub fn extend(&mut self, other: Symbols) {
+        self.labels.extend(other.labels);
+        self.lines.extend(other.lines);
+    }
+
+    pub fn address_of(&self, label: &str) -> Option<u16> {
+        self.labels.get(label).copied()
+    }
+
+    // The closest label at or before `addr` and how far `addr` is past it,
+    // only for addresses in the assembled code
+    pub fn label_before(&self, addr: u16) -> Option<(&str, u16)> {
+        let (line, _) = self.lines.range(..=addr).next_back()?;
+        if (addr - line) as usize >= MAX_INSTRUCTION_SIZE {
+            return None;
+        }
+
+        self.labels.iter()
+            .filter(|(_, &at)| at <= addr)
+            .max_by(|(a, at_a), (b, at_b)| at_a.cmp(at_b).then(b.cmp(a)))
+            .map(|(label, &at)| (label.as_str(), addr - at))
+    }
+
+    pub fn line_at(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(&addr)
+    }
+
+    // The first address assembled from `line` of `file`, or from the next line that has one,
+    // along with the line it's from
+    pub fn address_of_line(&self, file: &str, line: usize) -> Option<(u16, usize)> {
+        self.lines.iter()
+            .filter(|(_, source)| source.file == file && source.line >= line)
+            .min_by_key(|(&addr, source)| (source.line, addr))
+            .map(|(&addr, source)| (addr, source.line))
+    }
+
+    // `addr` followed by the label it's at or after, like `12 (@loop+3)`
+    pub fn describe(&self, addr: u16) -> String {
+        match self.label_before(addr) {
+            Some((label, 0)) => format!("{} ({})", addr, label),
+            Some((label, offset)) => format!("{} ({}+{})", addr, label, offset),
+            None => addr.to_string(),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::devices::Device;
+
+// Every address a u16 can hold
+pub const ADDRESS_SPACE_SIZE: usize = 0x10000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BusError {
+    Unmapped(u16),
+    Overlap(u16),
+    OutOfRange(u16),
+    UnclaimedPort(u8),
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Unmapped(addr) => write!(f, "no device mapped at address {}", addr),
+            BusError::Overlap(addr) => write!(f, "region at address {} overlaps an existing mapping", addr),
+            BusError::OutOfRange(addr) => write!(f, "region at address {} doesn't fit into the address space", addr),
+            BusError::UnclaimedPort(port) => write!(f, "no device claimed port {}", port),
+        }
+    }
+}
+
+struct Region {
+    start: usize,
+    end: usize, // exclusive
+    device: Box<dyn Device>,
+}
+
+/// A bus that maps devices (RAM, ROM, peripherals) onto address ranges,
+/// everything the CPU reads or writes goes through one.
+#[derive(Default)]
+pub struct MemoryBus {
+    regions: Vec<Region>,
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        Self { regions: Vec::new() }
+    }
+
+    pub fn map(&mut self, start: u16, device: Box<dyn Device>) -> Result<(), BusError> {
+        let start_idx = start as usize;
+        let end = start_idx + device.size();
+
+        if end > ADDRESS_SPACE_SIZE {
+            return Err(BusError::OutOfRange(start));
+        }
+
+        for region in &self.regions {
+            if start_idx < region.end && region.start < end {
+                return Err(BusError::Overlap(start));
+            }
+        }
+
+        self.regions.push(Region { start: start_idx, end, device });
+        Ok(())
+    }
+
+    // One past the highest mapped address
+    pub fn size(&self) -> usize {
+        self.regions.iter().map(|r| r.end).max().unwrap_or(0)
+    }
+
+    pub fn tick(&mut self) {
+        for region in &mut self.regions {
+            region.device.tick();
+        }
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.regions.iter().any(|r| r.device.interrupt_pending())
+    }
+
+    pub fn cacheable(&self, addr: u16) -> bool {
+        self.region(addr).is_some_and(|r| r.device.cacheable())
+    }
+
+    // Device states in mapping order
+    pub fn save_states(&self) -> Vec<Vec<u8>> {
+        self.regions.iter().map(|r| r.device.save_state()).collect()
+    }
+
+    // The same devices have to be mapped in the same order
+    pub fn check_states(&self, states: &[Vec<u8>]) -> Result<(), String> {
+        if states.len() != self.regions.len() {
+            return Err(format!("expected {} devices, got {}", self.regions.len(), states.len()));
+        }
+
+        for (region, state) in self.regions.iter().zip(states) {
+            region.device.check_state(state).map_err(|e| format!("device at {}: {}", region.start, e))?;
+        }
+
+        Ok(())
+    }
+
+    // Nothing is loaded unless every state fits
+    pub fn load_states(&mut self, states: &[Vec<u8>]) -> Result<(), String> {
+        self.check_states(states)?;
+
+        for (region, state) in self.regions.iter_mut().zip(states) {
+            region.device.load_state(state).map_err(|e| format!("device at {}: {}", region.start, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read(&mut self, addr: u16) -> Result<u8, BusError> {
+        let region = self.region_mut(addr).ok_or(BusError::Unmapped(addr))?;
+        let offset = (addr as usize - region.start) as u16;
+        Ok(region.device.read(offset))
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) -> Result<(), BusError> {
+        let region = self.region_mut(addr).ok_or(BusError::Unmapped(addr))?;
+        let offset = (addr as usize - region.start) as u16;
+        region.device.write(offset, value);
+        Ok(())
+    }
+
+    // Side effect free read, used by dumps and debuggers
+    pub fn peek(&self, addr: u16) -> Result<u8, BusError> {
+        let region = self.region(addr).ok_or(BusError::Unmapped(addr))?;
+        let offset = (addr as usize - region.start) as u16;
+        Ok(region.device.peek(offset))
+    }
+
+    fn region(&self, addr: u16) -> Option<&Region> {
+        let addr = addr as usize;
+        self.regions.iter().find(|r| r.start <= addr && addr < r.end)
+    }
+
+    fn region_mut(&mut self, addr: u16) -> Option<&mut Region> {
+        let addr = addr as usize;
+        self.regions.iter_mut().find(|r| r.start <= addr && addr < r.end)
+    }
+}
+
+/// A separate 256 port I/O space accessed with `in` and `out`.
+///
+/// Devices claim as many consecutive ports as their size.
+#[derive(Default)]
+pub struct PortBus {
+    space: MemoryBus,
+}
+
+impl PortBus {
+    pub fn new() -> Self {
+        Self { space: MemoryBus::new() }
+    }
+
+    pub fn claim(&mut self, port: u8, device: Box<dyn Device>) -> Result<(), BusError> {
+        if port as usize + device.size() > 0x100 {
+            return Err(BusError::OutOfRange(port as u16));
+        }
+
+        self.space.map(port as u16, device)
+    }
+
+    pub fn read(&mut self, port: u8) -> Result<u8, BusError> {
+        self.space.read(port as u16).map_err(|_| BusError::UnclaimedPort(port))
+    }
+
+    pub fn write(&mut self, port: u8, value: u8) -> Result<(), BusError> {
+        self.space.write(port as u16, value).map_err(|_| BusError::UnclaimedPort(port))
+    }
+
+    pub fn peek(&self, port: u8) -> Result<u8, BusError> {
+        self.space.peek(port as u16).map_err(|_| BusError::UnclaimedPort(port))
+    }
+
+    pub fn tick(&mut self) {
+        self.space.tick();
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.space.interrupt_pending()
+    }
+
+    pub fn save_states(&self) -> Vec<Vec<u8>> {
+        self.space.save_states()
+    }
+
+    pub fn check_states(&self, states: &[Vec<u8>]) -> Result<(), String> {
+        self.space.check_states(states)
+    }
+
+    pub fn load_states(&mut self, states: &[Vec<u8>]) -> Result<(), String> {
+        self.space.load_states(states)
+    }
+}
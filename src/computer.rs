@@ -1,35 +1,288 @@
-use std::{cmp::Ordering, usize, ops::Range};
+mod fault;
+pub use fault::*;
 
-use crate::instructions::{INSTRUCTIONS};
+mod syscalls;
+pub use syscalls::*;
+
+mod decode_cache;
+pub use decode_cache::DecodeCache;
+
+mod blocks;
+pub use blocks::BlockCache;
+
+mod protection;
+pub use protection::{Access, MemoryProtection, Permissions};
+
+mod loader;
+pub use loader::Segment;
+
+pub mod mmu;
+
+mod timing;
+pub use timing::Timing;
+
+mod pipeline;
+pub use pipeline::Pipeline;
+
+mod cache;
+pub use cache::Cache;
+
+mod predictor;
+pub use predictor::{BranchPredictor, BranchStats};
+
+mod watchpoints;
+pub use watchpoints::{RegisterCondition, WatchHit, Watchpoint, Watchpoints};
+
+mod history;
+pub use history::History;
+
+mod snapshot;
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+
+#[cfg(test)]
+mod tests;
+
+use std::{array, cmp::Ordering, collections::HashMap, io, mem, ops::Range, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{MemoryBus, PortBus};
+use crate::config::{ConfigError, MachineConfig};
+use crate::devices::{HostInput, Ram};
+use crate::instructions::{find_group, Decoded, InstructionSpec, Kind, INSTRUCTIONS, MAX_INSTRUCTION_SIZE, OPCODE_COUNT};
+
+use mmu::{PAGE_COUNT, PAGE_SIZE};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Engine {
+    // Fetches and decodes every instruction from the bus
+    Interpreter,
+    // Reuses decoded instructions until their bytes are written to
+    #[default]
+    Cached,
+    // Translates basic blocks into chains of closures
+    Block,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 3] = [Engine::Interpreter, Engine::Cached, Engine::Block];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Interpreter => "interpreter",
+            Engine::Cached => "cached",
+            Engine::Block => "block",
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Engine::ALL.into_iter()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("unknown engine {}", s))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Supervisor,
+    User, // no privileged instructions and only pages marked for user access
+}
+
+// Flags as debuggers and snapshots show them
+pub fn flags_name(flags: Ordering) -> &'static str {
+    match flags {
+        Ordering::Less => "less",
+        Ordering::Equal => "equal",
+        Ordering::Greater => "greater",
+    }
+}
+
+pub fn parse_flags(s: &str) -> Result<Ordering, String> {
+    [Ordering::Less, Ordering::Equal, Ordering::Greater].into_iter()
+        .find(|flags| flags_name(*flags) == s)
+        .ok_or_else(|| format!("invalid flags {}", s))
+}
 
 pub struct Computer {
-    pub memory: Vec<u8>,
-    
+    // Writing memory directly through the bus bypasses the decode cache,
+    // call `invalidate_decode_cache` afterwards
+    pub bus: MemoryBus,
+    pub ports: PortBus,
+    // Change it with `protect`, which keeps the decode cache consistent
+    pub protection: MemoryProtection,
+
     pub common_registers: Vec<u16>,
     pub flags: Ordering,
 
     pub ip: u16,
     pub should_halt: bool,
+    pub exit_code: Option<u16>,
+
+    pub engine: Engine,
+    pub decode_cache: DecodeCache,
+    pub blocks: BlockCache,
+    // Physical pages cached instructions were decoded from
+    code_pages: [bool; PAGE_COUNT],
+    pub instructions_retired: u64,
+    pub cycles: u64,
+    pub timing: Timing,
+    pub pipeline: Option<Pipeline>,
+    // Data accesses are charged to the instruction making them
+    pub icache: Option<Cache>,
+    pub dcache: Option<Cache>,
+    memory_stall: u64,
+    // Set by jumps, a jump to the next instruction is still taken
+    jumped: bool,
+    pub predictor: Option<BranchPredictor>,
+    // Change them with `watch` and `unwatch`
+    pub watchpoints: Watchpoints,
+    // Instructions can be undone while there is one
+    pub history: Option<History>,
+
+    pub interrupts_enabled: bool,
+    pub interrupt_vector: u16,
+    pub saved_ip: u16,
+    pub saved_flags: Ordering,
+    pub saved_interrupts_enabled: bool,
+
+    // Interrupts and traps switch to supervisor mode, `iret` returns to the saved mode.
+    // Change it with `set_mode`, which keeps the decode cache consistent
+    pub mode: Mode,
+    pub saved_mode: Mode,
+
+    // Faults are delivered to the guest while a trap table is installed,
+    // otherwise they are returned from `tick`
+    pub trap_table: Option<u16>,
+    pub trap_cause: u8,
+    pub fault_address: u16,
+
+    // Addresses are translated through the page table once one is installed,
+    // change it with `set_page_table`
+    pub has_mmu: bool,
+    pub page_table: Option<u16>,
+
+    // What the default syscalls print to and read from, the host's stdout and stdin by default
+    pub syscall_output: Box<dyn io::Write>,
+    pub syscall_input: Box<dyn io::Read>,
+    syscalls: HashMap<u8, SyscallHandler>,
+    // Indexed by opcode, holds only the enabled instruction groups
+    instructions: [Option<&'static InstructionSpec>; OPCODE_COUNT],
 }
 
 // Other
 impl Computer {
     pub fn new(mem_size: usize) -> Self {
-        Self {
-            memory: vec![0; mem_size],
+        let mut bus = MemoryBus::new();
+        bus.map(0, Box::new(Ram::new(mem_size))).unwrap();
+
+        Self::with_bus(bus)
+    }
+
+    pub fn with_config(config: &MachineConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        let mut computer = Self::new(config.memory_size);
+        computer.configure(config)?;
+
+        Ok(computer)
+    }
+
+    // Applies everything but the memory size, which is up to whoever built the bus
+    pub fn configure(&mut self, config: &MachineConfig) -> Result<(), ConfigError> {
+        config.validate()?;
+
+        self.common_registers = vec![0; config.registers];
+        if let Some(sp) = config.initial_sp {
+            *self.common_registers.last_mut().unwrap() = sp;
+        }
+
+        self.ip = config.reset_vector;
+        self.has_mmu = config.mmu;
+        self.timing = Timing::new(&config.timing);
+        self.pipeline = config.pipeline.as_ref().map(Pipeline::new);
+        self.icache = config.icache.as_ref().map(Cache::new);
+        self.dcache = config.dcache.as_ref().map(Cache::new);
+        self.predictor = config.predictor.as_ref().map(BranchPredictor::new);
+        self.set_page_table(None);
+
+        for region in &config.regions {
+            self.protect(region.start, region.size, region.permissions);
+        }
+
+        self.instructions = [None; OPCODE_COUNT];
+        for group in config.groups.iter().filter_map(|name| find_group(name)) {
+            for spec in group.specs {
+                self.instructions[spec.opcode as usize] = Some(spec);
+            }
+        }
+        self.invalidate_decode_cache();
+
+        Ok(())
+    }
+
+    pub fn with_bus(bus: MemoryBus) -> Self {
+        let mut computer = Self {
+            bus,
+            ports: PortBus::new(),
+            protection: MemoryProtection::new(),
 
             common_registers: vec![0, 0, 0, 0],
             flags: Ordering::Equal,
 
             ip: 0,
             should_halt: false,
-        }
+            exit_code: None,
+
+            engine: Engine::default(),
+            decode_cache: DecodeCache::new(),
+            blocks: BlockCache::new(),
+            code_pages: [false; PAGE_COUNT],
+            instructions_retired: 0,
+            cycles: 0,
+            timing: Timing::default(),
+            pipeline: None,
+            icache: None,
+            dcache: None,
+            memory_stall: 0,
+            jumped: false,
+            predictor: None,
+            watchpoints: Watchpoints::default(),
+            history: None,
+
+            interrupts_enabled: false,
+            interrupt_vector: 0,
+            saved_ip: 0,
+            saved_flags: Ordering::Equal,
+            saved_interrupts_enabled: false,
+
+            mode: Mode::Supervisor,
+            saved_mode: Mode::Supervisor,
+
+            trap_table: None,
+            trap_cause: 0,
+            fault_address: 0,
+
+            has_mmu: false,
+            page_table: None,
+
+            syscall_output: Box::new(io::stdout()),
+            syscall_input: Box::new(HostInput),
+            syscalls: HashMap::new(),
+            instructions: *INSTRUCTIONS,
+        };
+        computer.install_default_syscalls();
+
+        computer
     }
 
-    pub fn load_program(&mut self, prg: Vec<u8>) {
-        for i in 0..prg.len() {
-            self.memory[i] = prg[i];
-        }
+    pub fn load_program(&mut self, prg: Vec<u8>) -> Result<(), Fault> {
+        self.load_at(0, &prg)
     }
 
     pub fn dump(&self) {
@@ -42,24 +295,319 @@ impl Computer {
 
     pub fn dump_memory(&self, r: Range<usize>) {
         for i in r {
-            print!("{} | ", self.memory[i]);
+            match self.bus.peek(i as u16) {
+                Ok(byte) => print!("{} | ", byte),
+                Err(_) => print!("-- | "),
+            }
         }
         println!();
     }
+}
+
+// Memory access
+impl Computer {
+    // Memory protection applies to physical addresses
+    pub fn read_byte(&mut self, addr: u16) -> Result<u8, Fault> {
+        let paddr = self.translate(addr, Access::Read)?;
+        self.protection.check(paddr, Access::Read)?;
+        self.watchpoints.access(addr, Access::Read);
+        if let Some(dcache) = &mut self.dcache {
+            self.memory_stall += dcache.access(paddr, false);
+        }
+        Ok(self.bus.read(paddr)?)
+    }
+
+    pub fn write_byte(&mut self, addr: u16, value: u8) -> Result<(), Fault> {
+        let paddr = self.translate(addr, Access::Write)?;
+        self.protection.check(paddr, Access::Write)?;
+        self.watchpoints.access(addr, Access::Write);
+        if let Some(dcache) = &mut self.dcache {
+            self.memory_stall += dcache.access(paddr, true);
+        }
+
+        self.invalidate_written(addr, paddr);
+        self.record_write(paddr);
+        Ok(self.bus.write(paddr, value)?)
+    }
+
+    // Cached instructions are looked up by virtual address,
+    // with a page table code can also be written through another mapping of its page
+    fn invalidate_written(&mut self, vaddr: u16, paddr: u16) {
+        let aliased = self.page_table.is_some() && self.code_pages[paddr as usize / PAGE_SIZE];
+        if aliased || self.in_page_table(paddr) {
+            self.invalidate_decode_cache();
+        } else {
+            self.decode_cache.invalidate(vaddr);
+            self.blocks.invalidate(vaddr);
+        }
+    }
+
+    // For debuggers, any mapped byte can be read without protection checks or cache accesses
+    pub fn peek_byte(&self, addr: u16) -> Result<u8, Fault> {
+        let paddr = self.translate_mapped(addr)?;
+        Ok(self.bus.peek(paddr)?)
+    }
+
+    pub fn poke_byte(&mut self, addr: u16, value: u8) -> Result<(), Fault> {
+        let paddr = self.translate_mapped(addr)?;
+        self.invalidate_written(addr, paddr);
+        Ok(self.bus.write(paddr, value)?)
+    }
+
+    pub fn invalidate_decode_cache(&mut self) {
+        self.decode_cache.clear();
+        self.blocks.clear();
+        self.code_pages = [false; PAGE_COUNT];
+    }
+
+    // Cached instructions were only checked for execute permission when decoded
+    pub fn protect(&mut self, start: u16, size: usize, perms: Permissions) {
+        let start = start as usize;
+        self.protection.set(start..start + size, perms);
+        self.invalidate_decode_cache();
+    }
+
+    pub fn read_port(&mut self, port: u8) -> Result<u8, Fault> {
+        Ok(self.ports.read(port)?)
+    }
+
+    pub fn write_port(&mut self, port: u8, value: u8) -> Result<(), Fault> {
+        Ok(self.ports.write(port, value)?)
+    }
+
+    // Instruction fetch, which only needs execute permission
+    pub fn next_byte(&mut self) -> Result<u8, Fault> {
+        let paddr = self.translate(self.ip, Access::Execute)?;
+        self.protection.check(paddr, Access::Execute)?;
+        let ret = self.bus.read(paddr)?;
+        self.ip = self.ip.wrapping_add(1);
+        Ok(ret)
+    }
+
+    // Like `next_byte`, for decoding ahead of execution without touching devices
+    fn peek_next_byte(&mut self) -> Result<u8, Fault> {
+        let paddr = self.translate(self.ip, Access::Execute)?;
+        self.protection.check(paddr, Access::Execute)?;
+        let ret = self.bus.peek(paddr)?;
+        self.ip = self.ip.wrapping_add(1);
+        Ok(ret)
+    }
+}
+
+// Execution
+impl Computer {
+    // A fault is returned only if the guest didn't handle it,
+    // `ip` is left pointing at the faulting instruction
+    pub fn tick(&mut self) -> Result<bool, Fault> {
+        self.begin_record();
+
+        let start = self.ip;
+        // Before the instruction runs, it might change the mapping
+        let fetched = self.icache.is_some().then(|| self.fetch_addresses(start));
+
+        match self.step() {
+            Ok(decoded) => {
+                let spec = decoded.spec;
+                let taken = mem::take(&mut self.jumped);
+
+                let fetch_stall = match (fetched, &mut self.icache) {
+                    (Some(paddrs), Some(icache)) => icache.fetch(paddrs.into_iter().take(spec.size()).flatten()),
+                    _ => 0,
+                };
+                let memory_stall = mem::take(&mut self.memory_stall);
+
+                self.watchpoints.fetched(start, spec.size());
+                self.watchpoints.check_registers(&self.common_registers);
+
+                // Fetch goes the wrong way after taken branches, unless they're predicted
+                let mut redirected = taken;
+                let mut cost = self.timing.cost(spec, taken);
+                if let (Some(predictor), Kind::Branch) = (&mut self.predictor, spec.kind) {
+                    let mispredicted = predictor.observe(start, taken);
+
+                    // Charging for mispredictions replaces the taken branch cost
+                    if let Some(penalty) = predictor.penalty {
+                        redirected = mispredicted;
+                        cost = self.timing.cost(spec, false) + if mispredicted { penalty as u64 } else { 0 };
+                    }
+                }
+
+                self.instructions_retired += 1;
+                match &mut self.pipeline {
+                    Some(pipeline) => self.cycles = pipeline.issue(start, decoded, redirected, fetch_stall, memory_stall),
+                    None => self.cycles += cost + fetch_stall + memory_stall,
+                }
+            },
+            Err(fault) => {
+                self.ip = start;
+                let trapped = self.trap(fault);
+                // Faulting instructions aren't charged, neither are their accesses or the trap table lookup
+                self.memory_stall = 0;
+                self.jumped = false;
+                trapped?;
+                if let Some(pipeline) = &mut self.pipeline {
+                    pipeline.flush();
+                }
+            },
+        }
+
+        self.bus.tick();
+        self.ports.tick();
+        if self.interrupts_enabled && (self.bus.interrupt_pending() || self.ports.interrupt_pending()) {
+            self.interrupt();
+        }
+
+        Ok(! self.should_halt)
+    }
+
+    // Physical addresses of the bytes an instruction at `vaddr` can take up
+    fn fetch_addresses(&mut self, vaddr: u16) -> [Option<u16>; MAX_INSTRUCTION_SIZE] {
+        array::from_fn(|i| self.translate(vaddr.wrapping_add(i as u16), Access::Execute).ok())
+    }
+
+    // Returns the instruction that was executed
+    fn step(&mut self) -> Result<Decoded, Fault> {
+        let decoded = match self.engine {
+            Engine::Interpreter => self.fetch()?,
+            Engine::Cached => self.fetch_cached()?,
+            Engine::Block => return self.step_block(),
+        };
+
+        self.check_privilege(decoded.spec)?;
+        (decoded.spec.execute)(self, decoded.operands)?;
+        Ok(decoded)
+    }
+
+    pub(crate) fn jump(&mut self, target: u16) {
+        self.ip = target;
+        self.jumped = true;
+    }
+
+    fn fetch_cached(&mut self) -> Result<Decoded, Fault> {
+        let start = self.ip;
+        if let Some(decoded) = self.decode_cache.get(start) {
+            self.ip = start.wrapping_add(decoded.spec.size() as u16);
+            return Ok(decoded);
+        }
+
+        let decoded = self.fetch()?;
+        // Device registers can change on their own
+        if (0..decoded.spec.size() as u16).all(|i| self.cacheable(start.wrapping_add(i))) {
+            self.decode_cache.insert(start, decoded);
+        }
+
+        Ok(decoded)
+    }
+
+    // Device registers can change on their own, so instructions in them aren't cached,
+    // the pages of the others are remembered
+    fn cacheable(&mut self, vaddr: u16) -> bool {
+        let Ok(paddr) = self.translate(vaddr, Access::Execute) else {
+            return false;
+        };
+
+        let cacheable = self.bus.cacheable(paddr);
+        if cacheable {
+            self.code_pages[paddr as usize / PAGE_SIZE] = true;
+        }
+        cacheable
+    }
+
+    fn fetch(&mut self) -> Result<Decoded, Fault> {
+        self.decode_with(Self::next_byte)
+    }
+
+    pub(super) fn fetch_ahead(&mut self) -> Result<Decoded, Fault> {
+        self.decode_with(Self::peek_next_byte)
+    }
+
+    fn decode_with(&mut self, mut next_byte: impl FnMut(&mut Self) -> Result<u8, Fault>) -> Result<Decoded, Fault> {
+        let first_byte = next_byte(self)?;
+        let spec = self.instructions[(first_byte >> 2) as usize].ok_or(Fault::IllegalInstruction(first_byte))?;
+
+        let mut bytes = [0; MAX_INSTRUCTION_SIZE];
+        bytes[0] = first_byte;
+        for byte in bytes.iter_mut().take(spec.size()).skip(1) {
+            *byte = next_byte(self)?;
+        }
+
+        let operands = spec.format.decode(&bytes);
+        for reg in [operands.reg1, operands.reg2].into_iter().take(spec.format.register_count()) {
+            if reg >= self.common_registers.len() {
+                return Err(Fault::InvalidRegister(reg));
+            }
+        }
+
+        Ok(Decoded { spec, operands })
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        // Cached instructions were fetched with the permissions of the old mode
+        if mode != self.mode && self.page_table.is_some() {
+            self.invalidate_decode_cache();
+        }
+        self.mode = mode;
+    }
+
+    pub(crate) fn check_privilege(&self, spec: &InstructionSpec) -> Result<(), Fault> {
+        if spec.privileged && self.mode == Mode::User {
+            return Err(Fault::PrivilegedInstruction(spec.mnemonic));
+        }
+
+        Ok(())
+    }
+
+    fn enter_handler(&mut self, handler: u16) {
+        self.saved_ip = self.ip;
+        self.saved_flags = self.flags;
+        self.saved_interrupts_enabled = self.interrupts_enabled;
+        self.saved_mode = self.mode;
+        self.interrupts_enabled = false;
+        self.set_mode(Mode::Supervisor);
+        self.ip = handler;
+    }
+
+    // Handlers run with interrupts disabled until `iret`
+    fn interrupt(&mut self) {
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.flush();
+        }
+        self.enter_handler(self.interrupt_vector);
+    }
+
+    // The trap table holds a little-endian handler address per cause, 0 if there is none
+    fn trap_handler(&mut self, cause: u8) -> Option<u16> {
+        let entry = self.trap_table?.wrapping_add(cause as u16 * 2);
+
+        // The table belongs to the supervisor, looking it up doesn't trigger watchpoints,
+        // cache accesses or device reads
+        let mode = self.mode;
+        self.mode = Mode::Supervisor;
+        let lo = self.peek_trap_table(entry);
+        let hi = self.peek_trap_table(entry.wrapping_add(1));
+        self.mode = mode;
+
+        let (lo, hi) = (lo.ok()? as u16, hi.ok()? as u16);
+
+        match (hi << 8) | lo {
+            0 => None,
+            handler => Some(handler),
+        }
+    }
 
-    pub fn next_byte(&mut self) -> u8 {
-        let ret = self.memory[self.ip as usize];
-        self.ip += 1;
-        ret
+    fn peek_trap_table(&mut self, addr: u16) -> Result<u8, Fault> {
+        let paddr = self.translate(addr, Access::Read)?;
+        self.protection.check(paddr, Access::Read)?;
+        Ok(self.bus.peek(paddr)?)
     }
 
-    pub fn tick(&mut self) -> bool {
-        let byte = self.next_byte();
-        let instr = (byte & 0b11111100) >> 2;
-        INSTRUCTIONS.get(&instr).unwrap().execute(self, byte);
+    fn trap(&mut self, fault: Fault) -> Result<(), Fault> {
+        let handler = self.trap_handler(fault.cause()).ok_or(fault)?;
 
-        self.dump();
+        self.trap_cause = fault.cause();
+        self.fault_address = fault.address();
+        self.enter_handler(handler);
 
-        ! self.should_halt
+        Ok(())
     }
 }
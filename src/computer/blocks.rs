@@ -0,0 +1,209 @@
+use std::rc::Rc;
+
+use crate::instructions::{Decoded, MAX_INSTRUCTION_SIZE};
+
+use super::{Computer, Fault};
+
+// Longer straight-line runs are split into several blocks
+const MAX_BLOCK_LENGTH: usize = 64;
+
+type Op = Box<dyn Fn(&mut Computer) -> Result<(), Fault>>;
+
+struct CompiledInstruction {
+    addr: u16,
+    decoded: Decoded,
+    run: Op,
+}
+
+// A straight-line run of instructions translated into a chain of closures
+struct Block {
+    start: u16,
+    end: u16, // exclusive
+    instrs: Vec<CompiledInstruction>,
+}
+
+impl Block {
+    fn contains(&self, addr: u16) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+fn compile(decoded: Decoded, next_ip: u16) -> Op {
+    let Decoded { spec, operands } = decoded;
+    let execute = spec.execute;
+
+    if spec.privileged {
+        return Box::new(move |computer| {
+            computer.check_privilege(spec)?;
+            computer.ip = next_ip;
+            execute(computer, operands)
+        });
+    }
+
+    Box::new(move |computer| {
+        computer.ip = next_ip;
+        execute(computer, operands)
+    })
+}
+
+/// Translated blocks, indexed by their start address.
+///
+/// Writes through the `Computer` to an address covered by a block drop it,
+/// so self-modifying code gets translated again.
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: Vec<Option<Rc<Block>>>,
+    count: usize,
+    covered: Vec<u16>, // how many blocks cover each address
+    // Bumped whenever a block is dropped
+    generation: u64,
+    // The block and index of the instruction expected to run next
+    cursor: Option<(Rc<Block>, usize)>,
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn get(&self, start: u16) -> Option<&Rc<Block>> {
+        self.blocks.get(start as usize)?.as_ref()
+    }
+
+    fn insert(&mut self, block: Rc<Block>) {
+        let end = block.end as usize;
+        if end > self.covered.len() {
+            self.covered.resize(end, 0);
+            self.blocks.resize(end, None);
+        }
+        for addr in block.start..block.end {
+            self.covered[addr as usize] += 1;
+        }
+
+        let start = block.start as usize;
+        if self.blocks[start].replace(block).is_none() {
+            self.count += 1;
+        }
+    }
+
+    fn remove(&mut self, start: u16) {
+        if let Some(block) = self.blocks[start as usize].take() {
+            for addr in block.start..block.end {
+                self.covered[addr as usize] -= 1;
+            }
+            self.count -= 1;
+        }
+    }
+
+    pub fn invalidate(&mut self, addr: u16) {
+        if self.covered.get(addr as usize).copied().unwrap_or(0) == 0 {
+            return;
+        }
+
+        // Blocks never span more than MAX_BLOCK_LENGTH full-size instructions
+        let first = addr.saturating_sub((MAX_BLOCK_LENGTH * MAX_INSTRUCTION_SIZE) as u16);
+        for start in first..=addr {
+            if self.get(start).is_some_and(|b| b.contains(addr)) {
+                self.remove(start);
+            }
+        }
+
+        self.generation += 1;
+        self.cursor = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.covered.clear();
+        self.count = 0;
+        self.generation += 1;
+        self.cursor = None;
+    }
+}
+
+impl Computer {
+    // Translation stops at anything that may redirect control flow,
+    // at memory that isn't cacheable and right before an instruction that faults.
+    // Decoding ahead doesn't read from devices, which may change what they hold
+    fn translate_block(&mut self, start: u16) -> Result<Rc<Block>, Fault> {
+        let saved_ip = self.ip;
+        self.ip = start;
+
+        let mut instrs = Vec::new();
+        let mut addr = start;
+        let mut failure = None;
+
+        while instrs.len() < MAX_BLOCK_LENGTH {
+            let decoded = match self.fetch_ahead() {
+                Ok(decoded) => decoded,
+                Err(fault) => {
+                    failure = Some(fault);
+                    break;
+                },
+            };
+
+            let size = decoded.spec.size() as u16;
+            if !(0..size).all(|i| self.cacheable(addr.wrapping_add(i))) || addr.checked_add(size).is_none() {
+                break;
+            }
+
+            instrs.push(CompiledInstruction { addr, decoded, run: compile(decoded, self.ip) });
+            addr += size;
+
+            if decoded.spec.kind.ends_block() {
+                break;
+            }
+        }
+
+        self.ip = saved_ip;
+
+        if instrs.is_empty() {
+            return Err(failure.unwrap_or(Fault::IllegalInstruction(0)));
+        }
+
+        Ok(Rc::new(Block { start, end: addr, instrs }))
+    }
+
+    pub(super) fn step_block(&mut self) -> Result<Decoded, Fault> {
+        let ip = self.ip;
+
+        let (block, idx) = match self.blocks.cursor.take() {
+            Some((block, idx)) if block.instrs[idx].addr == ip => (block, idx),
+            _ => match self.blocks.get(ip) {
+                Some(block) => (block.clone(), 0),
+                None => match self.translate_block(ip) {
+                    Ok(block) => {
+                        self.blocks.insert(block.clone());
+                        (block, 0)
+                    },
+                    // Code that can't be translated runs through the interpreter
+                    Err(_) => {
+                        let decoded = self.fetch()?;
+                        self.check_privilege(decoded.spec)?;
+                        (decoded.spec.execute)(self, decoded.operands)?;
+                        return Ok(decoded);
+                    },
+                },
+            },
+        };
+
+        let generation = self.blocks.generation;
+        let decoded = block.instrs[idx].decoded;
+        (block.instrs[idx].run)(self)?;
+
+        // The instruction may have written over its own block
+        if idx + 1 < block.instrs.len() && self.blocks.generation == generation {
+            self.blocks.cursor = Some((block, idx + 1));
+        }
+
+        Ok(decoded)
+    }
+}
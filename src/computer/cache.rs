@@ -0,0 +1,139 @@
+use crate::config::{CacheConfig, Replacement, WritePolicy};
+
+#[derive(Clone, Copy, Default)]
+struct Line {
+    valid: bool,
+    dirty: bool,
+    tag: usize,
+    stamp: u64, // last access for LRU, when it was filled for FIFO
+}
+
+/// A set-associative cache in front of physical memory.
+///
+/// Only which lines are cached is modelled, the data itself always comes from the bus,
+/// so a cache changes the cycle count but never what a program does.
+pub struct Cache {
+    line_size: usize,
+    sets: usize,
+    ways: usize,
+    lines: Vec<Line>, // the ways of every set next to each other
+    replacement: Replacement,
+    write_policy: WritePolicy,
+    hit_latency: u32,
+    miss_penalty: u32,
+
+    clock: u64,
+    seed: u32, // for random replacement, the same every run
+
+    pub hits: u64,
+    pub misses: u64,
+    pub writebacks: u64,
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Self {
+        let lines = config.size / config.line_size;
+
+        Self {
+            line_size: config.line_size,
+            sets: lines / config.associativity,
+            ways: config.associativity,
+            lines: vec![Line::default(); lines],
+            replacement: config.replacement,
+            write_policy: config.write_policy,
+            hit_latency: config.hit_latency,
+            miss_penalty: config.miss_penalty,
+
+            clock: 0,
+            seed: 0x2545_F491,
+
+            hits: 0,
+            misses: 0,
+            writebacks: 0,
+        }
+    }
+
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        self.hits as f64 / self.accesses().max(1) as f64
+    }
+
+    // Returns the cycles the access takes on top of the instruction
+    pub fn access(&mut self, paddr: u16, write: bool) -> u64 {
+        self.clock += 1;
+
+        let line = paddr as usize / self.line_size;
+        let (set, tag) = (line % self.sets, line / self.sets);
+        let ways = set * self.ways..(set + 1) * self.ways;
+        let write_through = write && self.write_policy == WritePolicy::WriteThrough;
+
+        let mut cycles = self.hit_latency as u64;
+        if write_through {
+            cycles += self.miss_penalty as u64;
+        }
+
+        if let Some(hit) = self.lines[ways.clone()].iter_mut().find(|l| l.valid && l.tag == tag) {
+            self.hits += 1;
+            if self.replacement == Replacement::Lru {
+                hit.stamp = self.clock;
+            }
+            hit.dirty |= write && !write_through;
+            return cycles;
+        }
+
+        self.misses += 1;
+        if write_through {
+            return cycles;
+        }
+
+        let victim = ways.start + self.victim(set);
+        let evicted = self.lines[victim];
+        if evicted.valid && evicted.dirty {
+            self.writebacks += 1;
+            cycles += self.miss_penalty as u64;
+        }
+
+        self.lines[victim] = Line { valid: true, dirty: write, tag, stamp: self.clock };
+        cycles + self.miss_penalty as u64
+    }
+
+    // Every line an instruction's bytes are in is accessed once
+    pub fn fetch(&mut self, paddrs: impl Iterator<Item = u16>) -> u64 {
+        let mut cycles = 0;
+        let mut last_line = None;
+
+        for paddr in paddrs {
+            let line = paddr as usize / self.line_size;
+            if last_line != Some(line) {
+                cycles += self.access(paddr, false);
+                last_line = Some(line);
+            }
+        }
+
+        cycles
+    }
+
+    // The way a new line goes into, free ones are used first
+    fn victim(&mut self, set: usize) -> usize {
+        let ways = &self.lines[set * self.ways..(set + 1) * self.ways];
+        if let Some(free) = ways.iter().position(|l| !l.valid) {
+            return free;
+        }
+
+        match self.replacement {
+            Replacement::Lru | Replacement::Fifo => {
+                (0..self.ways).min_by_key(|&way| ways[way].stamp).unwrap()
+            },
+            Replacement::Random => {
+                // xorshift
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+                self.seed as usize % self.ways
+            },
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::instructions::{Decoded, MAX_INSTRUCTION_SIZE};
+
+/// Decoded instructions indexed by address.
+///
+/// Entries are dropped when any byte they were decoded from is written through the `Computer`.
+#[derive(Default)]
+pub struct DecodeCache {
+    entries: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, addr: u16) -> Option<Decoded> {
+        self.entries.get(addr as usize).copied().flatten()
+    }
+
+    pub fn insert(&mut self, addr: u16, decoded: Decoded) {
+        let idx = addr as usize;
+        if idx >= self.entries.len() {
+            self.entries.resize(idx + 1, None);
+        }
+        self.entries[idx] = Some(decoded);
+    }
+
+    pub fn invalidate(&mut self, addr: u16) {
+        // A write can land in any byte of an instruction
+        for back in 0..MAX_INSTRUCTION_SIZE as u16 {
+            if let Some(entry) = self.entries.get_mut(addr.wrapping_sub(back) as usize) {
+                *entry = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use crate::bus::BusError;
+
+use super::Access;
+
+// Cause codes, also the index into the guest trap table
+pub const CAUSE_ILLEGAL_INSTRUCTION: u8 = 0;
+pub const CAUSE_DIVIDE_BY_ZERO: u8 = 1;
+pub const CAUSE_BUS_ERROR: u8 = 2;
+pub const CAUSE_SYSCALL: u8 = 3;
+pub const CAUSE_PROTECTION: u8 = 4;
+pub const CAUSE_PAGE_FAULT: u8 = 5;
+pub const CAUSE_PRIVILEGED_INSTRUCTION: u8 = 6;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Fault {
+    IllegalInstruction(u8),
+    InvalidRegister(usize),
+    InvalidSystemRegister(u8),
+    DivideByZero,
+    UnknownSyscall(u8),
+    Bus(BusError),
+    Protection(Access, u16),
+    PageFault(Access, u16), // the virtual address
+    PrivilegedInstruction(&'static str),
+}
+
+impl Fault {
+    pub fn cause(&self) -> u8 {
+        match self {
+            Fault::IllegalInstruction(_)
+            | Fault::InvalidRegister(_)
+            | Fault::InvalidSystemRegister(_)
+            | Fault::UnknownSyscall(_) => CAUSE_ILLEGAL_INSTRUCTION,
+            Fault::DivideByZero => CAUSE_DIVIDE_BY_ZERO,
+            Fault::Bus(_) => CAUSE_BUS_ERROR,
+            Fault::Protection(..) => CAUSE_PROTECTION,
+            Fault::PageFault(..) => CAUSE_PAGE_FAULT,
+            Fault::PrivilegedInstruction(_) => CAUSE_PRIVILEGED_INSTRUCTION,
+        }
+    }
+
+    // The offending address, if there is one
+    pub fn address(&self) -> u16 {
+        match self {
+            Fault::Bus(BusError::Unmapped(addr)) => *addr,
+            Fault::Bus(BusError::UnclaimedPort(port)) => *port as u16,
+            Fault::Protection(_, addr) | Fault::PageFault(_, addr) => *addr,
+            _ => 0,
+        }
+    }
+}
+
+impl From<BusError> for Fault {
+    fn from(e: BusError) -> Self {
+        Fault::Bus(e)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::IllegalInstruction(byte) => write!(f, "illegal instruction {:#04x}", byte),
+            Fault::InvalidRegister(reg) => write!(f, "there is no register r{}", reg),
+            Fault::InvalidSystemRegister(sr) => write!(f, "there is no system register {}", sr),
+            Fault::DivideByZero => write!(f, "division by zero"),
+            Fault::UnknownSyscall(n) => write!(f, "there is no handler for syscall {}", n),
+            Fault::Bus(e) => write!(f, "bus error: {}", e),
+            Fault::Protection(access, addr) => write!(f, "{} access to address {} isn't permitted", access, addr),
+            Fault::PageFault(access, addr) => write!(f, "page fault on {} access to address {}", access, addr),
+            Fault::PrivilegedInstruction(mnemonic) => write!(f, "{} is only allowed in supervisor mode", mnemonic),
+        }
+    }
+}
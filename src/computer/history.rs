@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MAX_REGISTERS;
+
+use super::{snapshot::ordering, Computer, Mode};
+
+// Everything an instruction can change besides memory
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct State {
+    registers: [u16; MAX_REGISTERS],
+    #[serde(with = "ordering")]
+    flags: Ordering,
+    ip: u16,
+    should_halt: bool,
+    exit_code: Option<u16>,
+
+    interrupts_enabled: bool,
+    interrupt_vector: u16,
+    saved_ip: u16,
+    #[serde(with = "ordering")]
+    saved_flags: Ordering,
+    saved_interrupts_enabled: bool,
+    mode: Mode,
+    saved_mode: Mode,
+
+    trap_table: Option<u16>,
+    trap_cause: u8,
+    fault_address: u16,
+    page_table: Option<u16>,
+}
+
+struct Record {
+    state: State, // before the instruction
+    writes: Vec<(u16, u8)>, // physical addresses and the bytes they held
+}
+
+/// An undo log of the last instructions, the oldest are dropped once `limit` is reached.
+///
+/// Devices and the timing models aren't rewound, so cycle counts and statistics keep going
+/// and rewinding across I/O doesn't take back what a device saw.
+pub struct History {
+    records: VecDeque<Record>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self { records: VecDeque::new(), limit }
+    }
+
+    // How many instructions can be undone
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub(super) fn record_write(&mut self, paddr: u16, old: u8) {
+        if let Some(record) = self.records.back_mut() {
+            record.writes.push((paddr, old));
+        }
+    }
+}
+
+impl Computer {
+    pub(super) fn state(&self) -> State {
+        let mut registers = [0; MAX_REGISTERS];
+        for (saved, reg) in registers.iter_mut().zip(&self.common_registers) {
+            *saved = *reg;
+        }
+
+        State {
+            registers,
+            flags: self.flags,
+            ip: self.ip,
+            should_halt: self.should_halt,
+            exit_code: self.exit_code,
+
+            interrupts_enabled: self.interrupts_enabled,
+            interrupt_vector: self.interrupt_vector,
+            saved_ip: self.saved_ip,
+            saved_flags: self.saved_flags,
+            saved_interrupts_enabled: self.saved_interrupts_enabled,
+            mode: self.mode,
+            saved_mode: self.saved_mode,
+
+            trap_table: self.trap_table,
+            trap_cause: self.trap_cause,
+            fault_address: self.fault_address,
+            page_table: self.page_table,
+        }
+    }
+
+    // Caches are invalidated by the caller
+    pub(super) fn restore(&mut self, state: &State) {
+        for (reg, saved) in self.common_registers.iter_mut().zip(state.registers) {
+            *reg = saved;
+        }
+
+        self.flags = state.flags;
+        self.ip = state.ip;
+        self.should_halt = state.should_halt;
+        self.exit_code = state.exit_code;
+
+        self.interrupts_enabled = state.interrupts_enabled;
+        self.interrupt_vector = state.interrupt_vector;
+        self.saved_ip = state.saved_ip;
+        self.saved_flags = state.saved_flags;
+        self.saved_interrupts_enabled = state.saved_interrupts_enabled;
+        self.mode = state.mode;
+        self.saved_mode = state.saved_mode;
+
+        self.trap_table = state.trap_table;
+        self.trap_cause = state.trap_cause;
+        self.fault_address = state.fault_address;
+        self.page_table = state.page_table;
+    }
+
+    pub(super) fn begin_record(&mut self) {
+        let Some(mut history) = self.history.take() else {
+            return;
+        };
+
+        if history.records.len() == history.limit {
+            history.records.pop_front();
+        }
+        if history.limit > 0 {
+            history.records.push_back(Record { state: self.state(), writes: Vec::new() });
+        }
+
+        self.history = Some(history);
+    }
+
+    // Only memory that doesn't change on its own is restored
+    pub(super) fn record_write(&mut self, paddr: u16) {
+        if let Some(history) = &mut self.history {
+            if self.bus.cacheable(paddr) {
+                if let Ok(old) = self.bus.peek(paddr) {
+                    history.record_write(paddr, old);
+                }
+            }
+        }
+    }
+
+    // Undoes instructions until `stop` holds or the history runs out, returns how many were undone
+    pub fn rewind_until(&mut self, mut stop: impl FnMut(&Computer) -> bool) -> usize {
+        let mut undone = 0;
+
+        while let Some(record) = self.history.as_mut().and_then(|h| h.records.pop_back()) {
+            for (paddr, old) in record.writes.into_iter().rev() {
+                let _ = self.bus.write(paddr, old);
+            }
+            self.restore(&record.state);
+            undone += 1;
+
+            if stop(self) {
+                break;
+            }
+        }
+
+        if undone > 0 {
+            self.invalidate_decode_cache();
+            self.watchpoints.sync_registers(&self.common_registers);
+        }
+        undone
+    }
+
+    pub fn rewind(&mut self, count: usize) -> usize {
+        if count == 0 {
+            return 0;
+        }
+
+        let mut left = count;
+        self.rewind_until(|_| {
+            left -= 1;
+            left == 0
+        })
+    }
+
+    // Returns false if there's nothing to undo
+    pub fn step_back(&mut self) -> bool {
+        self.rewind(1) == 1
+    }
+}
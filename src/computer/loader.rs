@@ -0,0 +1,55 @@
+use crate::bus::{BusError, ADDRESS_SPACE_SIZE};
+
+use super::{Computer, Fault, Permissions};
+
+/// A piece of a program, loaded at a fixed address.
+pub struct Segment {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    // Leaves the permissions of the memory it's loaded to alone if `None`
+    pub permissions: Option<Permissions>,
+}
+
+impl Segment {
+    pub fn new(addr: u16, bytes: Vec<u8>) -> Self {
+        Self { addr, bytes, permissions: None }
+    }
+}
+
+impl Computer {
+    // Loading ignores memory protection and either copies every byte or nothing
+    pub fn load_at(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Fault> {
+        if addr as usize + bytes.len() > ADDRESS_SPACE_SIZE {
+            return Err(BusError::OutOfRange(addr).into());
+        }
+
+        for i in 0..bytes.len() {
+            self.bus.peek(addr + i as u16)?;
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            self.bus.write(addr + i as u16, *byte)?;
+        }
+        self.invalidate_decode_cache();
+
+        Ok(())
+    }
+
+    pub fn load_segment(&mut self, segment: &Segment) -> Result<(), Fault> {
+        self.load_at(segment.addr, &segment.bytes)?;
+
+        if let Some(perms) = segment.permissions {
+            self.protect(segment.addr, segment.bytes.len(), perms);
+        }
+
+        Ok(())
+    }
+
+    // Segments are loaded in order, later ones may overlap earlier ones
+    pub fn load_segments(&mut self, segments: &[Segment]) -> Result<(), Fault> {
+        for segment in segments {
+            self.load_segment(segment)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use crate::bus::ADDRESS_SPACE_SIZE;
+
+use super::{Access, Computer, Fault, Mode};
+
+pub const PAGE_SIZE: usize = 256;
+pub const PAGE_COUNT: usize = ADDRESS_SPACE_SIZE / PAGE_SIZE;
+pub const PTE_SIZE: usize = 2;
+pub const PAGE_TABLE_SIZE: usize = PAGE_COUNT * PTE_SIZE;
+
+// Flags in the first byte of a page table entry, the second one holds the physical page number
+pub const PTE_VALID: u8 = 1;
+pub const PTE_READ: u8 = 2;
+pub const PTE_WRITE: u8 = 4;
+pub const PTE_EXECUTE: u8 = 8;
+pub const PTE_USER: u8 = 16; // accessible in user mode
+
+fn required_flag(access: Access) -> u8 {
+    match access {
+        Access::Read => PTE_READ,
+        Access::Write => PTE_WRITE,
+        Access::Execute => PTE_EXECUTE,
+    }
+}
+
+impl Computer {
+    // Virtual addresses are physical ones while no page table is installed
+    pub fn translate(&mut self, vaddr: u16, access: Access) -> Result<u16, Fault> {
+        let Some(table) = self.page_table else {
+            return Ok(vaddr);
+        };
+
+        let entry = table.wrapping_add((vaddr >> 8) * PTE_SIZE as u16);
+        let flags = self.bus.read(entry)?;
+        let frame = self.bus.read(entry.wrapping_add(1))?;
+
+        let user_denied = self.mode == Mode::User && flags & PTE_USER == 0;
+        if flags & PTE_VALID == 0 || flags & required_flag(access) == 0 || user_denied {
+            return Err(Fault::PageFault(access, vaddr));
+        }
+
+        Ok(((frame as u16) << 8) | (vaddr & 0xFF))
+    }
+
+    // Debuggers see every mapped page, whatever its permissions
+    pub fn translate_mapped(&self, vaddr: u16) -> Result<u16, Fault> {
+        let Some(table) = self.page_table else {
+            return Ok(vaddr);
+        };
+
+        let entry = table.wrapping_add((vaddr >> 8) * PTE_SIZE as u16);
+        let flags = self.bus.peek(entry)?;
+        let frame = self.bus.peek(entry.wrapping_add(1))?;
+
+        if flags & PTE_VALID == 0 {
+            return Err(Fault::PageFault(Access::Read, vaddr));
+        }
+
+        Ok(((frame as u16) << 8) | (vaddr & 0xFF))
+    }
+
+    pub(super) fn in_page_table(&self, paddr: u16) -> bool {
+        self.page_table.is_some_and(|table| {
+            let offset = paddr.wrapping_sub(table) as usize;
+            offset < PAGE_TABLE_SIZE
+        })
+    }
+
+    // Cached translations of instructions don't survive a new page table
+    pub fn set_page_table(&mut self, table: Option<u16>) {
+        self.page_table = table;
+        self.invalidate_decode_cache();
+    }
+}
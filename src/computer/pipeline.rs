@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use crate::config::PipelineConfig;
+use crate::instructions::{Decoded, Kind, Location};
+
+pub const STAGES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+const IF: usize = 0;
+const ID: usize = 1;
+const EX: usize = 2;
+const MEM: usize = 3;
+const WB: usize = 4;
+
+const FLAGS: usize = 16; // tracked after the registers
+const DIAGRAM_COLUMN: usize = 16;
+
+// When a result can be used
+#[derive(Clone, Copy, Default)]
+struct Ready {
+    forwarded: u64, // the first cycle an instruction can execute with it
+    written: u64, // the cycle it's written back, registers are read after that
+}
+
+// An instruction drawn in the diagram
+struct Drawn {
+    text: String,
+    stages: [u64; 5],
+}
+
+/// A classic 5-stage pipeline, timing the instructions as the interpreter executes them.
+///
+/// Every stage takes a cycle, IF and MEM longer on cache misses. Branches are resolved in EX and unconditional jumps in ID,
+/// instructions after them are fetched assuming they aren't taken, or as predicted.
+/// System instructions, traps and interrupts drain the pipeline.
+pub struct Pipeline {
+    forwarding: bool,
+
+    previous: [u64; 5], // cycles the previous instruction entered every stage in
+    fetch_after: u64, // instructions fetched earlier are flushed
+    results: [Ready; FLAGS + 1],
+
+    pub data_stalls: u64,
+    pub control_stalls: u64,
+
+    diagram: Option<Box<dyn Write>>,
+    drawn: VecDeque<Drawn>,
+    next_row: u64,
+}
+
+impl Pipeline {
+    pub fn new(config: &PipelineConfig) -> Self {
+        Self {
+            forwarding: config.forwarding,
+
+            previous: [0; 5],
+            fetch_after: 1,
+            results: [Ready::default(); FLAGS + 1],
+
+            data_stalls: 0,
+            control_stalls: 0,
+
+            diagram: None,
+            drawn: VecDeque::new(),
+            next_row: 1,
+        }
+    }
+
+    // Prints what every stage holds in every cycle
+    pub fn show_diagram(&mut self, out: Box<dyn Write>) {
+        self.diagram = Some(out);
+    }
+
+    // The cycle the last instruction was written back in
+    pub fn cycles(&self) -> u64 {
+        self.previous[WB]
+    }
+
+    fn location(decoded: &Decoded, loc: Location) -> usize {
+        match loc {
+            Location::RegA => decoded.operands.reg1,
+            Location::RegB => decoded.operands.reg2,
+            Location::Reg(reg) => reg,
+            Location::Flags => FLAGS,
+        }
+    }
+
+    // Returns the cycle the instruction is written back in, `redirected` if the instructions
+    // fetched after it are flushed. Cache misses keep it in IF or MEM for the extra cycles
+    pub fn issue(&mut self, addr: u16, decoded: Decoded, redirected: bool, fetch_stall: u64, memory_stall: u64) -> u64 {
+        let prev = self.previous;
+        let spec = decoded.spec;
+        let sources: Vec<Ready> = spec.dataflow.reads.iter()
+            .map(|loc| self.results[Self::location(&decoded, *loc)])
+            .collect();
+
+        let mut stages = [0; 5];
+
+        // A stage is free once the previous instruction has moved on
+        let fetch = prev[ID].max(prev[IF] + 1);
+        stages[IF] = fetch.max(self.fetch_after);
+        // Cycles the previous instruction was stalled in ID count as data hazards
+        let unstalled = fetch.max(prev[EX].saturating_sub(1));
+        self.control_stalls += stages[IF].saturating_sub(unstalled);
+
+        stages[ID] = (stages[IF] + 1 + fetch_stall).max(prev[EX]);
+
+        // Instructions wait in ID until their operands are ready,
+        // registers written back in a cycle can be read in the same one
+        let execute = (stages[ID] + 1).max(prev[MEM]);
+        let ready = sources.iter()
+            .map(|r| if self.forwarding { r.forwarded } else { r.written + 1 })
+            .fold(execute, u64::max);
+        stages[EX] = ready;
+        self.data_stalls += ready - execute;
+
+        stages[MEM] = (stages[EX] + 1).max(prev[WB]);
+        stages[WB] = (stages[MEM] + 1 + memory_stall).max(prev[WB] + 1);
+
+        // Loaded values are only there after MEM
+        let forwarded = match spec.kind {
+            Kind::Load | Kind::Io => stages[WB],
+            _ => stages[EX] + 1,
+        };
+        for loc in spec.dataflow.writes {
+            self.results[Self::location(&decoded, *loc)] = Ready { forwarded, written: stages[WB] };
+        }
+
+        match spec.kind {
+            Kind::Jump if redirected => self.fetch_after = stages[ID] + 1,
+            Kind::Branch if redirected => self.fetch_after = stages[EX] + 1,
+            Kind::System => self.flush(),
+            _ => {},
+        }
+
+        self.previous = stages;
+
+        if self.diagram.is_some() {
+            let operands = spec.format.format_operands(&decoded.operands).join(" ");
+            let text = format!("{} {} {}", addr, spec.mnemonic, operands);
+            self.draw(Drawn { text: text.trim_end().to_string(), stages });
+        }
+
+        stages[WB]
+    }
+
+    // Nothing is fetched until every instruction in flight is done
+    pub fn flush(&mut self) {
+        self.fetch_after = self.fetch_after.max(self.previous[WB] + 1);
+    }
+
+    fn draw(&mut self, instr: Drawn) {
+        // Rows before the new instruction is fetched can't change anymore
+        self.draw_rows(instr.stages[IF]);
+        self.drawn.push_back(instr);
+    }
+
+    // Draws the rows that are still missing
+    pub fn finish(&mut self) {
+        self.draw_rows(self.previous[WB] + 1);
+    }
+
+    fn draw_rows(&mut self, until: u64) {
+        let Some(out) = self.diagram.as_mut() else {
+            return;
+        };
+
+        if self.next_row == 1 && self.next_row < until {
+            let header: String = STAGES.iter().map(|s| format!("{:<w$}", s, w = DIAGRAM_COLUMN)).collect();
+            let _ = writeln!(out, "{:>6}  {}", "cycle", header.trim_end());
+        }
+
+        for cycle in self.next_row..until {
+            let mut row = String::new();
+
+            for stage in 0..STAGES.len() {
+                let cell = self.drawn.iter().find(|d| {
+                    let leaves = if stage == WB { d.stages[WB] + 1 } else { d.stages[stage + 1] };
+                    (d.stages[stage]..leaves).contains(&cycle)
+                });
+
+                // Instructions that stay in a stage are stalled
+                let text = match cell {
+                    Some(d) if cycle > d.stages[stage] => format!("{}*", d.text),
+                    Some(d) => d.text.clone(),
+                    None => String::from("-"),
+                };
+                row.push_str(&format!("{:<w$}", text, w = DIAGRAM_COLUMN));
+            }
+
+            let _ = writeln!(out, "{:>6}  {}", cycle, row.trim_end());
+        }
+        self.next_row = self.next_row.max(until);
+
+        while self.drawn.front().is_some_and(|d| d.stages[WB] < self.next_row) {
+            self.drawn.pop_front();
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use crate::config::{PredictorConfig, PredictorKind};
+
+const WEAKLY_NOT_TAKEN: u8 = 1;
+const STRONGLY_TAKEN: u8 = 3;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BranchStats {
+    pub executed: u64,
+    pub mispredicted: u64,
+}
+
+impl BranchStats {
+    pub fn accuracy(&self) -> f64 {
+        1.0 - self.mispredicted as f64 / self.executed.max(1) as f64
+    }
+}
+
+/// Predicts every conditional branch before it's resolved and learns from the outcome.
+pub struct BranchPredictor {
+    kind: PredictorKind,
+    table: Vec<u8>, // the last outcome for the 1-bit predictor, counters otherwise
+    history: usize, // outcomes of the last branches, the newest in the lowest bit
+    history_mask: usize,
+    pub penalty: Option<u32>,
+
+    pub total: BranchStats,
+    pub by_address: BTreeMap<u16, BranchStats>,
+}
+
+impl BranchPredictor {
+    pub fn new(config: &PredictorConfig) -> Self {
+        let initial = if config.kind == PredictorKind::OneBit { 0 } else { WEAKLY_NOT_TAKEN };
+
+        Self {
+            kind: config.kind,
+            table: vec![initial; config.table_size],
+            history: 0,
+            history_mask: (1 << config.history_bits) - 1,
+            penalty: config.penalty,
+
+            total: BranchStats::default(),
+            by_address: BTreeMap::new(),
+        }
+    }
+
+    fn index(&self, addr: u16) -> usize {
+        let index = match self.kind {
+            PredictorKind::Gshare => addr as usize ^ self.history,
+            _ => addr as usize,
+        };
+        index & (self.table.len() - 1)
+    }
+
+    pub fn predict(&self, addr: u16) -> bool {
+        let entry = self.table[self.index(addr)];
+        match self.kind {
+            PredictorKind::NotTaken => false,
+            PredictorKind::OneBit => entry != 0,
+            PredictorKind::TwoBit | PredictorKind::Gshare => entry > WEAKLY_NOT_TAKEN,
+        }
+    }
+
+    // Returns whether the branch was mispredicted
+    pub fn observe(&mut self, addr: u16, taken: bool) -> bool {
+        let mispredicted = self.predict(addr) != taken;
+
+        let index = self.index(addr);
+        let entry = &mut self.table[index];
+        match self.kind {
+            PredictorKind::NotTaken => {},
+            PredictorKind::OneBit => *entry = taken as u8,
+            PredictorKind::TwoBit | PredictorKind::Gshare => {
+                *entry = if taken { (*entry + 1).min(STRONGLY_TAKEN) } else { entry.saturating_sub(1) };
+            },
+        }
+        self.history = ((self.history << 1) | taken as usize) & self.history_mask;
+
+        for stats in [&mut self.total, self.by_address.entry(addr).or_default()] {
+            stats.executed += 1;
+            stats.mispredicted += mispredicted as u64;
+        }
+
+        mispredicted
+    }
+}
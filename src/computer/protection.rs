@@ -0,0 +1,153 @@
+use std::fmt;
+use std::ops::{BitOr, Range};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::ADDRESS_SPACE_SIZE;
+
+use super::Fault;
+
+/// What may be done with a byte of memory.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Permissions(u8);
+
+impl Permissions {
+    pub const NONE: Self = Self(0);
+    pub const READ: Self = Self(1);
+    pub const WRITE: Self = Self(2);
+    pub const EXECUTE: Self = Self(4);
+    pub const ALL: Self = Self(7);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Permissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+// Written like `r-x`, dashes are optional
+impl FromStr for Permissions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars().try_fold(Permissions::NONE, |perms, ch| match ch {
+            'r' => Ok(perms | Permissions::READ),
+            'w' => Ok(perms | Permissions::WRITE),
+            'x' => Ok(perms | Permissions::EXECUTE),
+            '-' => Ok(perms),
+            _ => Err(format!("invalid permissions {}", s)),
+        })
+    }
+}
+
+impl TryFrom<String> for Permissions {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Permissions> for String {
+    fn from(perms: Permissions) -> Self {
+        perms.to_string()
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (perm, ch) in [(Permissions::READ, 'r'), (Permissions::WRITE, 'w'), (Permissions::EXECUTE, 'x')] {
+            write!(f, "{}", if self.contains(perm) { ch } else { '-' })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    pub(super) fn permission(self) -> Permissions {
+        match self {
+            Access::Read => Permissions::READ,
+            Access::Write => Permissions::WRITE,
+            Access::Execute => Permissions::EXECUTE,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// Permissions of every address, everything is allowed until restricted.
+/// Regions without any permissions serve as guards.
+pub struct MemoryProtection {
+    perms: Vec<Permissions>,
+}
+
+impl Default for MemoryProtection {
+    fn default() -> Self {
+        Self { perms: vec![Permissions::ALL; ADDRESS_SPACE_SIZE] }
+    }
+}
+
+impl MemoryProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, addr: u16) -> Permissions {
+        self.perms[addr as usize]
+    }
+
+    // Parts of `range` outside the address space are ignored
+    pub fn set(&mut self, range: Range<usize>, perms: Permissions) {
+        let end = range.end.min(ADDRESS_SPACE_SIZE);
+        if range.start < end {
+            self.perms[range.start..end].fill(perms);
+        }
+    }
+
+    // Runs of addresses with the same permissions, except those allowing everything
+    pub fn regions(&self) -> Vec<(Range<usize>, Permissions)> {
+        let mut regions: Vec<(Range<usize>, Permissions)> = Vec::new();
+
+        for (addr, perms) in self.perms.iter().enumerate() {
+            match regions.last_mut() {
+                Some((range, last)) if range.end == addr && last == perms => range.end += 1,
+                _ if *perms != Permissions::ALL => regions.push((addr..addr + 1, *perms)),
+                _ => {},
+            }
+        }
+
+        regions
+    }
+
+    pub fn check(&self, addr: u16, access: Access) -> Result<(), Fault> {
+        if self.get(addr).contains(access.permission()) {
+            Ok(())
+        } else {
+            Err(Fault::Protection(access, addr))
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::ADDRESS_SPACE_SIZE;
+use crate::config::RegionConfig;
+
+use super::history::State;
+use super::{Computer, Permissions};
+
+// Bumped whenever the format changes, older snapshots are rejected
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Parse(String),
+    Incompatible(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "couldn't access the snapshot: {}", e),
+            SnapshotError::Parse(e) => write!(f, "couldn't parse the snapshot: {}", e),
+            SnapshotError::Incompatible(e) => write!(f, "the snapshot doesn't fit this machine: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+// Flags are written as less, equal or greater
+pub(super) mod ordering {
+    use std::cmp::Ordering;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::computer::{flags_name, parse_flags};
+
+    pub fn serialize<S: Serializer>(flags: &Ordering, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(flags_name(*flags))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Ordering, D::Error> {
+        parse_flags(&String::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+// Device states are written as hex strings
+mod hex_list {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::hex;
+
+    pub fn serialize<S: Serializer>(states: &[Vec<u8>], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(states.iter().map(|state| hex::encode(state)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(d)?.iter()
+            .map(|s| hex::decode(s).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// The architectural state of a computer and its devices, as JSON.
+///
+/// The machine it's restored into needs the same registers and devices mapped the same way.
+/// Timing models, caches and statistics other than the instruction and cycle counts aren't included.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    pub version: u32,
+    registers: usize,
+    cpu: State,
+    instructions_retired: u64,
+    cycles: u64,
+    protection: Vec<RegionConfig>, // everything else allows all accesses
+    #[serde(with = "hex_list")]
+    memory: Vec<Vec<u8>>, // devices on the memory bus in mapping order
+    #[serde(with = "hex_list")]
+    ports: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize")
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, SnapshotError> {
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|e| SnapshotError::Parse(e.to_string()))?;
+
+        // Checked first, other versions may not even parse
+        let version = value.get("version").and_then(|v| v.as_u64());
+        if version != Some(SNAPSHOT_VERSION as u64) {
+            return Err(SnapshotError::Parse(format!("unsupported version {:?}, expected {}", version, SNAPSHOT_VERSION)));
+        }
+
+        serde_json::from_value(value).map_err(|e| SnapshotError::Parse(e.to_string()))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), SnapshotError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    pub fn load(path: &str) -> Result<Self, SnapshotError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+impl Computer {
+    pub fn snapshot(&self) -> Snapshot {
+        let protection = self.protection.regions().into_iter()
+            .map(|(range, permissions)| RegionConfig { start: range.start as u16, size: range.len(), permissions })
+            .collect();
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            registers: self.common_registers.len(),
+            cpu: self.state(),
+            instructions_retired: self.instructions_retired,
+            cycles: self.cycles,
+            protection,
+            memory: self.bus.save_states(),
+            ports: self.ports.save_states(),
+        }
+    }
+
+    // Nothing is changed if the snapshot doesn't fit
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if snapshot.registers != self.common_registers.len() {
+            return Err(SnapshotError::Incompatible(format!(
+                "{} registers instead of {}", snapshot.registers, self.common_registers.len(),
+            )));
+        }
+
+        self.bus.check_states(&snapshot.memory).map_err(SnapshotError::Incompatible)?;
+        self.ports.check_states(&snapshot.ports).map_err(SnapshotError::Incompatible)?;
+
+        self.bus.load_states(&snapshot.memory).map_err(SnapshotError::Incompatible)?;
+        self.ports.load_states(&snapshot.ports).map_err(SnapshotError::Incompatible)?;
+
+        self.restore(&snapshot.cpu);
+        self.instructions_retired = snapshot.instructions_retired;
+        self.cycles = snapshot.cycles;
+
+        self.protect(0, ADDRESS_SPACE_SIZE, Permissions::ALL);
+        for region in &snapshot.protection {
+            self.protect(region.start, region.size, region.permissions);
+        }
+
+        // Older instructions can't be undone into a different state
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+        self.watchpoints.sync_registers(&self.common_registers);
+        self.invalidate_decode_cache();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Computer, Fault, CAUSE_SYSCALL};
+
+// Arguments are passed and results returned in r0 and r1
+pub const SYS_EXIT: u8 = 0;       // halt with exit code r0
+pub const SYS_PRINT_INT: u8 = 1;  // print r0 in decimal
+pub const SYS_PRINT_CHAR: u8 = 2; // print the low byte of r0
+pub const SYS_READ_CHAR: u8 = 3;  // r0 = next input byte, 0xFFFF at the end of input
+pub const SYS_TIME: u8 = 4;       // r1:r0 = seconds since the Unix epoch
+
+pub type SyscallHandler = Box<dyn FnMut(&mut Computer) -> Result<(), Fault>>;
+
+fn get_register(computer: &Computer, reg: usize) -> Result<u16, Fault> {
+    computer.common_registers.get(reg).copied().ok_or(Fault::InvalidRegister(reg))
+}
+
+fn set_register(computer: &mut Computer, reg: usize, value: u16) -> Result<(), Fault> {
+    let slot = computer.common_registers.get_mut(reg).ok_or(Fault::InvalidRegister(reg))?;
+    *slot = value;
+    Ok(())
+}
+
+// The guest has no way to handle a failing host stream
+fn print(computer: &mut Computer, bytes: &[u8]) {
+    let output = &mut computer.syscall_output;
+    let _ = output.write_all(bytes).and_then(|_| output.flush());
+}
+
+fn read_byte(computer: &mut Computer) -> Option<u8> {
+    let mut byte = [0];
+    match computer.syscall_input.read(&mut byte) {
+        Ok(1) => Some(byte[0]),
+        _ => None,
+    }
+}
+
+impl Computer {
+    pub fn set_syscall<F>(&mut self, n: u8, handler: F)
+    where
+        F: FnMut(&mut Computer) -> Result<(), Fault> + 'static,
+    {
+        self.syscalls.insert(n, Box::new(handler));
+    }
+
+    pub fn remove_syscall(&mut self, n: u8) -> Option<SyscallHandler> {
+        self.syscalls.remove(&n)
+    }
+
+    pub fn install_default_syscalls(&mut self) {
+        self.set_syscall(SYS_EXIT, |c| {
+            c.exit_code = Some(get_register(c, 0)?);
+            c.should_halt = true;
+            Ok(())
+        });
+
+        self.set_syscall(SYS_PRINT_INT, |c| {
+            let value = get_register(c, 0)?;
+            print(c, value.to_string().as_bytes());
+            Ok(())
+        });
+
+        self.set_syscall(SYS_PRINT_CHAR, |c| {
+            let value = get_register(c, 0)?;
+            print(c, &[value as u8]);
+            Ok(())
+        });
+
+        self.set_syscall(SYS_READ_CHAR, |c| {
+            let byte = read_byte(c);
+            set_register(c, 0, byte.map_or(0xFFFF, |b| b as u16))
+        });
+
+        self.set_syscall(SYS_TIME, |c| {
+            let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            set_register(c, 0, secs as u16)?;
+            set_register(c, 1, (secs >> 16) as u16)
+        });
+    }
+
+    // A guest handler installed in the trap table takes priority over the host one,
+    // it gets the syscall number in the fault address register
+    pub fn syscall(&mut self, n: u8) -> Result<(), Fault> {
+        if let Some(handler) = self.trap_handler(CAUSE_SYSCALL) {
+            self.trap_cause = CAUSE_SYSCALL;
+            self.fault_address = n as u16;
+            self.enter_handler(handler);
+            return Ok(());
+        }
+
+        let mut handler = self.syscalls.remove(&n).ok_or(Fault::UnknownSyscall(n))?;
+        let result = handler(self);
+        // The handler may have replaced itself
+        self.syscalls.entry(n).or_insert(handler);
+
+        result
+    }
+}
@@ -0,0 +1,303 @@
+use std::cmp::Ordering;
+
+use crate::bus::MemoryBus;
+use crate::devices::{ConsoleInput, Ram, SharedBuffer};
+use crate::test_util::{assemble, machine, sample};
+
+use super::mmu::{PAGE_TABLE_SIZE, PTE_EXECUTE, PTE_READ, PTE_VALID, PTE_WRITE};
+use super::{Access, Computer, Engine, History, Permissions, RegisterCondition, Snapshot, WatchHit, Watchpoint};
+
+// Runaway programs fail instead of hanging the tests
+const MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+#[derive(PartialEq, Clone, Debug)]
+struct FinalState {
+    registers: Vec<u16>,
+    ip: u16,
+    flags: Ordering,
+    memory: Vec<u8>,
+    instructions_retired: u64,
+    output: String,
+}
+
+// `setup` changes the machine before the program starts
+fn run(program: &[u8], engine: Engine, setup: fn(&mut Computer)) -> FinalState {
+    let (mut comp, buffer) = machine(program, engine);
+    setup(&mut comp);
+    finish(&mut comp, &buffer)
+}
+
+fn finish(comp: &mut Computer, buffer: &SharedBuffer) -> FinalState {
+    while comp.tick().unwrap() {
+        assert!(comp.instructions_retired < MAX_INSTRUCTIONS, "{} engine didn't halt", comp.engine.name());
+    }
+    state(comp, buffer)
+}
+
+fn state(comp: &Computer, buffer: &SharedBuffer) -> FinalState {
+    FinalState {
+        registers: comp.common_registers.clone(),
+        ip: comp.ip,
+        flags: comp.flags,
+        memory: (0..=255).map(|addr| comp.peek_byte(addr).unwrap()).collect(),
+        instructions_retired: comp.instructions_retired,
+        output: String::from_utf8(buffer.contents()).unwrap(),
+    }
+}
+
+// Every engine has to end up where the interpreter does
+fn run_all(program: &[u8]) -> FinalState {
+    run_all_with(program, |_| {})
+}
+
+fn run_all_with(program: &[u8], setup: fn(&mut Computer)) -> FinalState {
+    let expected = run(program, Engine::Interpreter, setup);
+    for engine in Engine::ALL {
+        assert_eq!(run(program, engine, setup), expected, "{} engine", engine.name());
+    }
+
+    expected
+}
+
+#[test]
+fn factorial() {
+    let state = run_all(&sample("factorial.s"));
+    assert_eq!(state.output, "120\n");
+}
+
+#[test]
+fn loop_until_raw_data() {
+    let state = run_all(&sample("new.s"));
+    assert_eq!(state.registers[0], 50);
+}
+
+#[test]
+fn patching_an_executed_instruction() {
+    let (program, _) = assemble("patch-executed", "
+        put r2 0;
+        put r3 7;
+        @patch;
+        put r1 1; its value is at 8
+        put r0 8;
+        str r3 r0;
+        inc r2;
+        put r0 2;
+        cmp r2 r0;
+        jne @patch;
+        halt;
+    ");
+
+    let state = run_all(&program);
+    assert_eq!(state.registers[1], 7);
+}
+
+#[test]
+fn patching_an_instruction_ahead() {
+    let (program, _) = assemble("patch-ahead", "
+        put r3 9;
+        put r0 10;
+        str r3 r0;
+        put r1 1; its value is at 10
+        halt;
+    ");
+
+    let state = run_all(&program);
+    assert_eq!(state.registers[1], 9);
+}
+
+// Page 0 runs the program, page 1 maps the same frame writable
+fn alias_code_page(comp: &mut Computer) {
+    comp.bus.map(0x100, Box::new(Ram::new(PAGE_TABLE_SIZE))).unwrap();
+    comp.bus.write(0x100, PTE_VALID | PTE_READ | PTE_EXECUTE).unwrap();
+    comp.bus.write(0x102, PTE_VALID | PTE_READ | PTE_WRITE).unwrap();
+    comp.has_mmu = true;
+    comp.set_page_table(Some(0x100));
+}
+
+#[test]
+fn patching_an_executed_instruction_through_an_alias() {
+    let (program, _) = assemble("patch-alias", "
+        put r2 0;
+        @patch;
+        put r1 1; its value is at 5
+        put r0 128;
+        put r3 128;
+        add r0 r3;
+        put r3 5;
+        add r0 r3;
+        put r3 9;
+        str r3 r0;
+        inc r2;
+        put r0 2;
+        cmp r2 r0;
+        jne @patch;
+        halt;
+    ");
+
+    let state = run_all_with(&program, alias_code_page);
+    assert_eq!(state.registers[1], 9);
+}
+
+#[test]
+fn syscalls() {
+    let (program, _) = assemble("syscalls", "
+        put r0 42;
+        sys 1;
+        sys 3;
+        sys 1;
+        halt;
+    ");
+
+    let state = run_all(&program);
+    assert_eq!(state.output, "4265535");
+}
+
+#[test]
+fn watchpoint_on_a_write() {
+    let (program, symbols) = assemble("watch-write", "
+        put r0 7;
+        put r1 200;
+        ldr r2 r1;
+        @store;
+        str r0 r1;
+        @done;
+        halt;
+    ");
+    let (mut comp, _) = machine(&program, Engine::default());
+    let id = comp.watch(Watchpoint::Memory { range: 199..=201, accesses: Permissions::WRITE }).unwrap();
+    assert!(comp.watch(Watchpoint::Register { reg: 4, condition: RegisterCondition::Changes }).is_err());
+
+    // The read before it isn't watched
+    while comp.take_watch_hit().is_none() {
+        assert!(comp.tick().unwrap());
+    }
+    assert_eq!(comp.ip, symbols.labels["@done"]);
+    assert_eq!(comp.peek_byte(200), Ok(7));
+
+    comp.ip = symbols.labels["@store"];
+    comp.tick().unwrap();
+    assert_eq!(comp.take_watch_hit(), Some(WatchHit::Memory { id, addr: 200, access: Access::Write }));
+}
+
+// Everything rewinding restores, counts and output keep going
+fn undoable(state: FinalState) -> (Vec<u16>, u16, Ordering, Vec<u8>) {
+    (state.registers, state.ip, state.flags, state.memory)
+}
+
+#[test]
+fn rewind() {
+    let program = sample("factorial.s");
+    let (mut comp, buffer) = machine(&program, Engine::default());
+    comp.history = Some(History::new(100));
+    for _ in 0..5 {
+        comp.tick().unwrap();
+    }
+    let before = state(&comp, &buffer);
+
+    for _ in 0..20 {
+        comp.tick().unwrap();
+    }
+    assert_ne!(undoable(state(&comp, &buffer)), undoable(before.clone()));
+    assert_eq!(comp.rewind(20), 20);
+    assert_eq!(undoable(state(&comp, &buffer)), undoable(before));
+}
+
+#[test]
+fn rewind_restores_memory() {
+    let (program, _) = assemble("rewind-memory", "
+        put r0 7;
+        put r1 200;
+        str r0 r1;
+        halt;
+    ");
+    let (mut comp, buffer) = machine(&program, Engine::default());
+    comp.history = Some(History::new(100));
+    let before = state(&comp, &buffer);
+
+    let after = finish(&mut comp, &buffer);
+    assert_eq!(after.memory[200], 7);
+    assert_eq!(comp.rewind(4), 4);
+    assert_eq!(undoable(state(&comp, &buffer)), undoable(before));
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let program = sample("factorial.s");
+    let (mut comp, buffer) = machine(&program, Engine::default());
+    for _ in 0..10 {
+        comp.tick().unwrap();
+    }
+
+    let snapshot = Snapshot::from_json(&comp.snapshot().to_json()).unwrap();
+    let (mut restored, restored_buffer) = machine(&[], Engine::default());
+    restored.restore_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.snapshot().to_json(), comp.snapshot().to_json());
+
+    // All of the output comes after the snapshot
+    assert!(buffer.contents().is_empty());
+    assert_eq!(finish(&mut restored, &restored_buffer), finish(&mut comp, &buffer));
+}
+
+#[test]
+fn trap_table_lookups_are_not_reads() {
+    let (program, symbols) = assemble("trap-lookup", "
+        put r0 200;
+        wrsr 3 r0;
+        put r1 @handler;
+        put r0 202;
+        str r1 r0; division by zero
+        put r2 0;
+        div r1 r2;
+        @handler;
+        halt;
+    ");
+    let (mut comp, _) = machine(&program, Engine::default());
+    comp.watch(Watchpoint::Memory { range: 200..=215, accesses: Permissions::READ }).unwrap();
+
+    while comp.tick().unwrap() {
+        assert_eq!(comp.take_watch_hit(), None);
+    }
+    assert_eq!(comp.saved_ip, symbols.labels["@handler"] - 2);
+    assert_eq!(comp.take_watch_hit(), None);
+}
+
+#[test]
+fn jumps_to_the_next_instruction_are_taken() {
+    let cycles = |name, jump| {
+        let (program, _) = assemble(name, &format!("
+            put r0 0;
+            cmp r0 r0;
+            {} @next;
+            @next;
+            halt;
+        ", jump));
+        let (mut comp, _) = machine(&program, Engine::default());
+        while comp.tick().unwrap() {}
+        comp.cycles
+    };
+
+    assert_eq!(cycles("jump-next-taken", "je"), cycles("jump-next-not-taken", "jne") + 1);
+    assert_eq!(cycles("jump-next-always", "jmp"), cycles("jump-next-taken", "je"));
+}
+
+// The program runs on into console input, which holds a `halt`
+#[test]
+fn decoding_ahead_leaves_devices_alone() {
+    let (program, _) = assemble("decode-ahead", "
+        put r0 5;
+        inc r0;
+    ");
+    let (halt, _) = assemble("decode-ahead-halt", "halt;");
+
+    for engine in Engine::ALL {
+        let mut bus = MemoryBus::new();
+        bus.map(0, Box::new(Ram::new(program.len()))).unwrap();
+        bus.map(program.len() as u16, Box::new(ConsoleInput::from_bytes(vec![halt[0], 0xFF]))).unwrap();
+        let mut comp = Computer::with_bus(bus);
+        comp.engine = engine;
+        comp.load_program(program.clone()).unwrap();
+
+        while comp.tick().unwrap_or_else(|fault| panic!("{} engine: {}", engine.name(), fault)) {}
+        assert_eq!(comp.common_registers[0], 6);
+    }
+}
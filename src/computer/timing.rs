@@ -0,0 +1,38 @@
+use crate::config::TimingConfig;
+use crate::instructions::{all_instructions, InstructionSpec, Kind, OPCODE_COUNT};
+
+/// Cycles every instruction takes, resolved from a `TimingConfig`.
+pub struct Timing {
+    costs: [u32; OPCODE_COUNT], // indexed by opcode, memory accesses included
+    taken_branch: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::new(&TimingConfig::default())
+    }
+}
+
+impl Timing {
+    // Mnemonics the config doesn't know are ignored, `MachineConfig::validate` reports them
+    pub fn new(config: &TimingConfig) -> Self {
+        let mut costs = [config.default; OPCODE_COUNT];
+        for spec in all_instructions() {
+            let cost = &mut costs[spec.opcode as usize];
+
+            if let Some(c) = config.instructions.get(spec.mnemonic) {
+                *cost = *c;
+            }
+            if matches!(spec.kind, Kind::Load | Kind::Store) {
+                *cost += config.memory_access;
+            }
+        }
+
+        Self { costs, taken_branch: config.taken_branch }
+    }
+
+    pub fn cost(&self, spec: &InstructionSpec, taken: bool) -> u64 {
+        let extra = if taken { self.taken_branch } else { 0 };
+        (self.costs[spec.opcode as usize] + extra) as u64
+    }
+}
@@ -0,0 +1,164 @@
+use std::ops::RangeInclusive;
+
+use super::{Access, Computer, Fault, Permissions};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RegisterCondition {
+    Changes,
+    Equals(u16), // once it becomes equal
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Watchpoint {
+    // Virtual addresses, `accesses` says which accesses to stop at, so `-w-` watches writes
+    Memory { range: RangeInclusive<u16>, accesses: Permissions },
+    Register { reg: usize, condition: RegisterCondition },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum WatchHit {
+    Memory { id: usize, addr: u16, access: Access },
+    Register { id: usize, reg: usize, value: u16 },
+}
+
+struct Entry {
+    id: usize,
+    watchpoint: Watchpoint,
+    last: u16, // the register value after the previous instruction
+}
+
+/// Accesses and register changes to stop at, checked after every instruction.
+/// Only the first hit of an instruction is kept until it's taken.
+#[derive(Default)]
+pub struct Watchpoints {
+    entries: Vec<Entry>,
+    next_id: usize,
+    // Nothing is checked while there is nothing to watch
+    memory: bool,
+    registers: bool,
+    hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.entries.iter().map(|e| (e.id, &e.watchpoint))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn update(&mut self) {
+        self.memory = self.entries.iter().any(|e| matches!(e.watchpoint, Watchpoint::Memory { .. }));
+        self.registers = self.entries.iter().any(|e| matches!(e.watchpoint, Watchpoint::Register { .. }));
+    }
+
+    fn record(&mut self, hit: WatchHit) {
+        self.hit.get_or_insert(hit);
+    }
+
+    pub(super) fn access(&mut self, addr: u16, access: Access) {
+        if !self.memory {
+            return;
+        }
+
+        let hit = self.entries.iter().find_map(|e| match &e.watchpoint {
+            Watchpoint::Memory { range, accesses } if range.contains(&addr) && accesses.contains(access.permission()) => {
+                Some(WatchHit::Memory { id: e.id, addr, access })
+            },
+            _ => None,
+        });
+        if let Some(hit) = hit {
+            self.record(hit);
+        }
+    }
+
+    // Instructions may come from the decode cache, so fetches are reported once they're executed
+    pub(super) fn fetched(&mut self, start: u16, size: usize) {
+        for i in 0..size {
+            self.access(start.wrapping_add(i as u16), Access::Execute);
+        }
+    }
+
+    // Registers changed behind the instructions' back don't trigger anything,
+    // neither do ones a new config took away
+    pub(super) fn sync_registers(&mut self, registers: &[u16]) {
+        for entry in &mut self.entries {
+            if let Watchpoint::Register { reg, .. } = entry.watchpoint {
+                entry.last = registers.get(reg).copied().unwrap_or_default();
+            }
+        }
+    }
+
+    pub(super) fn check_registers(&mut self, registers: &[u16]) {
+        if !self.registers {
+            return;
+        }
+
+        let mut hit = None;
+        for entry in &mut self.entries {
+            let Watchpoint::Register { reg, condition } = entry.watchpoint else {
+                continue;
+            };
+            let Some(&value) = registers.get(reg) else {
+                continue;
+            };
+
+            let triggered = match condition {
+                RegisterCondition::Changes => value != entry.last,
+                RegisterCondition::Equals(expected) => value == expected && entry.last != expected,
+            };
+            entry.last = value;
+
+            if triggered && hit.is_none() {
+                hit = Some(WatchHit::Register { id: entry.id, reg, value });
+            }
+        }
+
+        if let Some(hit) = hit {
+            self.record(hit);
+        }
+    }
+}
+
+impl Computer {
+    // Returns the id to remove it with, ids start at 1
+    pub fn watch(&mut self, watchpoint: Watchpoint) -> Result<usize, String> {
+        let last = match watchpoint {
+            Watchpoint::Register { reg, .. } => {
+                *self.common_registers.get(reg).ok_or_else(|| format!("there is no register r{}", reg))?
+            },
+            Watchpoint::Memory { .. } => 0,
+        };
+
+        let watchpoints = &mut self.watchpoints;
+        watchpoints.next_id += 1;
+        let id = watchpoints.next_id;
+        watchpoints.entries.push(Entry { id, watchpoint, last });
+        watchpoints.update();
+
+        Ok(id)
+    }
+
+    // Returns false if there is no such watchpoint
+    pub fn unwatch(&mut self, id: usize) -> bool {
+        let watchpoints = &mut self.watchpoints;
+        let count = watchpoints.entries.len();
+        watchpoints.entries.retain(|e| e.id != id);
+        watchpoints.update();
+
+        watchpoints.entries.len() != count
+    }
+
+    // What the last instructions hit, if anything
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watchpoints.hit.take()
+    }
+
+    // For debuggers, the register's watchpoints don't take the new value for a change
+    pub fn set_register(&mut self, reg: usize, value: u16) -> Result<(), Fault> {
+        *self.common_registers.get_mut(reg).ok_or(Fault::InvalidRegister(reg))? = value;
+        self.watchpoints.sync_registers(&self.common_registers);
+        Ok(())
+    }
+}
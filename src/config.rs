@@ -0,0 +1,327 @@
+use std::{fmt, fs, io};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::ADDRESS_SPACE_SIZE;
+use crate::computer::Permissions;
+use crate::instructions::{find_group, is_mnemonic, ISA};
+
+pub const MAX_REGISTERS: usize = 16; // a register id takes a nibble
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "couldn't read the config: {}", e),
+            ConfigError::Parse(e) => write!(f, "couldn't parse the config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid config: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+// Cycles taken by every instruction
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingConfig {
+    pub default: u32, // for instructions not listed in `instructions`
+    pub memory_access: u32, // extra for `ldr` and `str`
+    pub taken_branch: u32, // extra for jumps that are taken
+    pub instructions: HashMap<String, u32>, // by mnemonic
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            default: 1,
+            memory_access: 1,
+            taken_branch: 1,
+            instructions: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub forwarding: bool, // stall until results are written back otherwise
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self { forwarding: true }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Replacement {
+    Lru,
+    Fifo,
+    Random,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WritePolicy {
+    WriteBack, // allocates lines on writes, dirty lines are written back when evicted
+    WriteThrough, // every write goes to memory, misses don't allocate
+}
+
+// A cache in front of memory, sizes are in bytes and powers of two
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub size: usize,
+    pub line_size: usize,
+    pub associativity: usize, // lines per set, 1 for direct mapped
+    pub replacement: Replacement,
+    pub write_policy: WritePolicy,
+    pub hit_latency: u32, // extra cycles for every access
+    pub miss_penalty: u32, // extra cycles for every line read from or written to memory
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            size: 64,
+            line_size: 8,
+            associativity: 1,
+            replacement: Replacement::Lru,
+            write_policy: WritePolicy::WriteBack,
+            hit_latency: 0,
+            miss_penalty: 10,
+        }
+    }
+}
+
+impl CacheConfig {
+    fn validate(&self, name: &str) -> Result<(), ConfigError> {
+        let invalid = |e: &str| Err(ConfigError::Invalid(format!("{}: {}", name, e)));
+
+        if !self.size.is_power_of_two() || self.size > ADDRESS_SPACE_SIZE {
+            return invalid("size must be a power of two up to the address space size");
+        }
+
+        if !self.line_size.is_power_of_two() || self.line_size > self.size {
+            return invalid("line size must be a power of two up to the cache size");
+        }
+
+        let lines = self.size / self.line_size;
+        if !lines.is_multiple_of(self.associativity) {
+            return invalid("associativity must divide the number of lines");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum PredictorKind {
+    NotTaken,
+    OneBit, // predicts what the branch did last time
+    TwoBit, // saturating counters
+    Gshare, // saturating counters indexed by the address and the global history
+}
+
+impl FromStr for PredictorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not-taken" => Ok(PredictorKind::NotTaken),
+            "one-bit" => Ok(PredictorKind::OneBit),
+            "two-bit" => Ok(PredictorKind::TwoBit),
+            "gshare" => Ok(PredictorKind::Gshare),
+            _ => Err(format!("unknown predictor {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for PredictorKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// Observes conditional branches
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PredictorConfig {
+    pub kind: PredictorKind,
+    pub table_size: usize, // entries, a power of two
+    pub history_bits: u32, // for gshare
+    // Cycles a misprediction costs instead of the taken branch cost, only statistics are kept without it
+    pub penalty: Option<u32>,
+}
+
+impl Default for PredictorConfig {
+    fn default() -> Self {
+        Self {
+            kind: PredictorKind::TwoBit,
+            table_size: 256,
+            history_bits: 8,
+            penalty: None,
+        }
+    }
+}
+
+// Memory with restricted permissions
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RegionConfig {
+    pub start: u16,
+    pub size: usize,
+    pub permissions: Permissions,
+}
+
+/// Describes a machine variant, missing fields keep their default values.
+///
+/// ```toml
+/// memory_size = 4096
+/// registers = 8
+/// reset_vector = 16
+/// initial_sp = 4096
+/// groups = ["misc", "basic_math", "data_movement", "branching"]
+/// mmu = true
+///
+/// [timing]
+/// memory_access = 2
+/// instructions = { mul = 4, div = 12 }
+///
+/// [pipeline]
+/// forwarding = false
+///
+/// [dcache]
+/// size = 128
+/// associativity = 2
+/// write_policy = "write-through"
+///
+/// [predictor]
+/// kind = "gshare"
+/// penalty = 2
+///
+/// [[regions]]
+/// start = 3840
+/// size = 256
+/// permissions = "---"
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineConfig {
+    pub memory_size: usize, // bytes of RAM mapped at address 0
+    pub registers: usize,
+    pub reset_vector: u16, // where execution starts
+    // There is no dedicated stack pointer, the last register serves as one
+    pub initial_sp: Option<u16>,
+    // Instructions from other groups fault as illegal
+    pub groups: Vec<String>,
+    // Makes the page table system register available
+    pub mmu: bool,
+    // Applied in order, everything else may be read, written and executed
+    pub regions: Vec<RegionConfig>,
+    pub timing: TimingConfig,
+    // Cycles are counted by a pipeline model instead of `timing` if there is one
+    pub pipeline: Option<PipelineConfig>,
+    // Instruction fetches and data accesses are uncached without them
+    pub icache: Option<CacheConfig>,
+    pub dcache: Option<CacheConfig>,
+    pub predictor: Option<PredictorConfig>,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            memory_size: 256,
+            registers: 4,
+            reset_vector: 0,
+            initial_sp: None,
+            groups: ISA.iter().map(|group| group.name.to_string()).collect(),
+            mmu: false,
+            regions: Vec::new(),
+            timing: TimingConfig::default(),
+            pipeline: None,
+            icache: None,
+            dcache: None,
+            predictor: None,
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        toml::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str(s).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    // .json files are read as JSON, anything else as TOML
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let s = fs::read_to_string(path)?;
+
+        if path.ends_with(".json") {
+            Self::from_json(&s)
+        } else {
+            Self::from_toml(&s)
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.memory_size == 0 || self.memory_size > ADDRESS_SPACE_SIZE {
+            return Err(ConfigError::Invalid(format!("memory size must be between 1 and {} bytes", ADDRESS_SPACE_SIZE)));
+        }
+
+        if self.registers == 0 || self.registers > MAX_REGISTERS {
+            return Err(ConfigError::Invalid(format!("register count must be between 1 and {}", MAX_REGISTERS)));
+        }
+
+        if let Some(group) = self.groups.iter().find(|g| find_group(g).is_none()) {
+            return Err(ConfigError::Invalid(format!("unknown instruction group {}", group)));
+        }
+
+        if let Some(mnemonic) = self.timing.instructions.keys().find(|m| !is_mnemonic(m)) {
+            return Err(ConfigError::Invalid(format!("timing given for unknown instruction {}", mnemonic)));
+        }
+
+        if let Some(region) = self.regions.iter().find(|r| r.start as usize + r.size > ADDRESS_SPACE_SIZE) {
+            return Err(ConfigError::Invalid(format!("region at {} doesn't fit into the address space", region.start)));
+        }
+
+        for (name, cache) in [("icache", &self.icache), ("dcache", &self.dcache)] {
+            if let Some(cache) = cache {
+                cache.validate(name)?;
+            }
+        }
+
+        if let Some(predictor) = &self.predictor {
+            if !predictor.table_size.is_power_of_two() || predictor.table_size > ADDRESS_SPACE_SIZE {
+                return Err(ConfigError::Invalid(String::from("predictor table size must be a power of two up to the address space size")));
+            }
+
+            if predictor.history_bits > 16 {
+                return Err(ConfigError::Invalid(String::from("predictor history can be at most 16 bits")));
+            }
+        }
+
+        Ok(())
+    }
+}
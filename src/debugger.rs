@@ -0,0 +1,203 @@
+mod repl;
+pub use repl::Repl;
+
+mod gdb;
+pub use gdb::{Connection, GdbStub, StdioConnection};
+
+mod dap;
+pub use dap::{read_message, DapServer, Launcher};
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeSet;
+
+use crate::assembler::Symbols;
+use crate::computer::{Computer, Fault, History, WatchHit};
+use crate::disassembler::disassemble_one;
+use crate::instructions::{decode, Kind, MAX_INSTRUCTION_SIZE, SYS_OPCODE};
+
+// Instructions that can be stepped back over
+pub const HISTORY_LIMIT: usize = 100_000;
+// Handlers that don't return give control back after this many instructions
+pub const STEP_OVER_LIMIT: usize = 1_000_000;
+
+// Why execution stopped
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(u16),
+    Halted,
+    Watchpoint(WatchHit), // after the instruction that hit it
+    Fault(Fault), // the guest didn't handle it, `ip` is left at the faulting instruction
+    HistoryStart, // nothing older to step back to
+}
+
+/// Runs a computer under control, stopping at breakpoints.
+/// Keeps a history of the last instructions unless the computer already has one.
+pub struct Debugger {
+    pub computer: Computer,
+    pub symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new(mut computer: Computer, symbols: Symbols) -> Self {
+        computer.history.get_or_insert_with(|| History::new(HISTORY_LIMIT));
+        Self { computer, symbols, breakpoints: BTreeSet::new() }
+    }
+
+    // A number or a label
+    pub fn resolve(&self, location: &str) -> Result<u16, String> {
+        if location.starts_with('@') {
+            self.symbols.address_of(location).ok_or_else(|| format!("no label {}", location))
+        } else {
+            location.parse().map_err(|_| format!("invalid address {}", location))
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    // Returns false if there already was one
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    fn tick(&mut self) -> Option<Stop> {
+        if self.computer.should_halt {
+            return Some(Stop::Halted);
+        }
+
+        let result = self.computer.tick();
+        let hit = self.computer.take_watch_hit();
+
+        match result {
+            Ok(_) if hit.is_some() => hit.map(Stop::Watchpoint),
+            Ok(true) => None,
+            Ok(false) => Some(Stop::Halted),
+            Err(fault) => Some(Stop::Fault(fault)),
+        }
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.tick().unwrap_or(Stop::Stepped)
+    }
+
+    // Runs until a breakpoint is hit or `until` holds for `ip`,
+    // the instruction at the current breakpoint is executed first
+    fn run(&mut self, mut until: impl FnMut(u16) -> bool) -> Stop {
+        loop {
+            if let Some(stop) = self.tick() {
+                return stop;
+            }
+
+            // A breakpoint wins, continuing again would run past it
+            let ip = self.computer.ip;
+            if self.breakpoints.contains(&ip) {
+                return Stop::Breakpoint(ip);
+            }
+            if until(ip) {
+                return Stop::Stepped;
+            }
+        }
+    }
+
+    pub fn cont(&mut self) -> Stop {
+        self.run(|_| false)
+    }
+
+    // Like `cont`, but gives control back with `Stop::Stepped` after `count` instructions
+    pub fn cont_for(&mut self, count: usize) -> Stop {
+        let mut left = count;
+        self.run(|_| {
+            left = left.saturating_sub(1);
+            left == 0
+        })
+    }
+
+    pub fn run_to(&mut self, addr: u16) -> Stop {
+        self.run(|ip| ip == addr)
+    }
+
+    // Like `step`, but the trap and interrupt handlers entered on the way run until they return,
+    // for at most STEP_OVER_LIMIT instructions
+    pub fn step_over(&mut self) -> Stop {
+        let ip = self.computer.ip;
+        let decoded = decode(&self.peek_bytes(ip, MAX_INSTRUCTION_SIZE));
+
+        match decoded.map(|d| d.spec) {
+            // Jumps can go anywhere, so can iret and other system instructions but sys
+            Some(spec) if !matches!(spec.kind, Kind::Jump | Kind::Branch | Kind::System) || spec.opcode == SYS_OPCODE => {
+                let fall_through = ip.wrapping_add(spec.size() as u16);
+                let mut left = STEP_OVER_LIMIT;
+                self.run(|ip| {
+                    left -= 1;
+                    ip == fall_through || left == 0
+                })
+            },
+            _ => self.step(),
+        }
+    }
+
+    // Undoes up to `count` instructions
+    pub fn step_back(&mut self, count: usize) -> Stop {
+        match self.computer.rewind(count) {
+            undone if undone == count => Stop::Stepped,
+            _ => Stop::HistoryStart,
+        }
+    }
+
+    // Undoes instructions until the previous breakpoint
+    pub fn reverse_cont(&mut self) -> Stop {
+        let breakpoints = &self.breakpoints;
+        let mut hit = None;
+        self.computer.rewind_until(|comp| {
+            hit = breakpoints.contains(&comp.ip).then_some(comp.ip);
+            hit.is_some()
+        });
+
+        hit.map_or(Stop::HistoryStart, Stop::Breakpoint)
+    }
+
+    // Stops at the first byte that can't be read
+    pub fn peek_bytes(&self, addr: u16, count: usize) -> Vec<u8> {
+        (0..count)
+            .map_while(|i| self.computer.peek_byte(addr.wrapping_add(i as u16)).ok())
+            .collect()
+    }
+
+    // The instructions starting at `addr`, bytes that don't decode are shown as data
+    pub fn disassemble(&self, addr: u16, count: usize) -> Vec<(u16, String)> {
+        let mut ret = Vec::new();
+        let mut addr = addr;
+
+        for _ in 0..count {
+            let bytes = self.peek_bytes(addr, MAX_INSTRUCTION_SIZE);
+            if bytes.is_empty() {
+                break;
+            }
+
+            let (text, size) = disassemble_one(&bytes);
+            ret.push((addr, text));
+            addr = addr.wrapping_add(size as u16);
+        }
+
+        ret
+    }
+
+    // Where disassembling should start to show `before` instructions ahead of `addr`,
+    // only known for assembled code
+    pub fn instructions_before(&self, addr: u16, before: usize) -> u16 {
+        self.symbols.lines.range(..addr)
+            .rev()
+            .take(before)
+            .last()
+            .map_or(addr, |(at, _)| *at)
+    }
+}
@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::bus::ADDRESS_SPACE_SIZE;
+use crate::computer::{flags_name, parse_flags};
+use crate::devices::SharedBuffer;
+
+use super::{Debugger, Stop};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+// Instructions run between checks for new requests
+const RUN_CHUNK: usize = 4096;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Creates the debugger for the program a client launches.
+pub type Launcher = Box<dyn FnMut(&str) -> Result<Debugger, String>>;
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut ret = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+
+    ret
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u32> = s.trim_end_matches('=').bytes()
+        .map(|c| BASE64.iter().position(|&d| d == c).map(|d| d as u32))
+        .collect::<Option<_>>()?;
+
+    let mut ret = Vec::new();
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let n = chunk.iter().enumerate().fold(0, |n, (i, &d)| n | d << (18 - 6 * i));
+        ret.extend((0..chunk.len() - 1).map(|i| (n >> (16 - 8 * i)) as u8));
+    }
+
+    Some(ret)
+}
+
+// Messages framed by a Content-Length header, None at the end of the input
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        match header.trim() {
+            "" if length.is_some() => break,
+            header => if let Some(len) = header.strip_prefix("Content-Length:") {
+                length = len.trim().parse().ok();
+            },
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Serves the Debug Adapter Protocol for programs created by a `Launcher`.
+///
+/// There's a single thread with a single frame, the registers are its variables
+/// and memory is referenced by decimal addresses.
+/// Breakpoints are set by source line, on the first instruction assembled from it or a later line.
+pub struct DapServer<W: Write> {
+    out: W,
+    seq: u64,
+    launcher: Launcher,
+    output: Option<SharedBuffer>, // the program's console, forwarded as output events
+    debugger: Option<Debugger>,
+    breakpoints: HashMap<PathBuf, Vec<u16>>, // by source file
+    events: Vec<Value>, // sent after the response to the current request
+    stop_on_entry: bool,
+    running: bool,
+    done: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(launcher: Launcher, output: Option<SharedBuffer>, out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            launcher,
+            output,
+            debugger: None,
+            breakpoints: HashMap::new(),
+            events: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+            done: false,
+        }
+    }
+
+    // Serves requests until the client disconnects, the program runs between them
+    pub fn run(&mut self, mut input: impl BufRead + Send + 'static) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            while let Ok(Some(message)) = read_message(&mut input) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        while !self.done {
+            let message = if self.running {
+                match rx.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                match rx.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => break,
+                }
+            };
+
+            match message {
+                Some(message) => self.handle(&message)?,
+                None => self.run_chunk()?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.events.push(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        for event in std::mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+        Ok(())
+    }
+
+    fn forward_output(&mut self) {
+        let Some(output) = &self.output else {
+            return;
+        };
+
+        let text = output.to_string_lossy();
+        output.clear();
+        if !text.is_empty() {
+            self.event("output", json!({ "category": "stdout", "output": text }));
+        }
+    }
+
+    fn handle(&mut self, message: &Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+
+        let command = message["command"].as_str().unwrap_or_default();
+        let args = &message["arguments"];
+        let result = self.request(command, args);
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": message["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(e) => response["message"] = json!(e),
+        }
+
+        self.send(response)?;
+        self.flush_events()
+    }
+
+    fn debugger(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger.as_mut().ok_or_else(|| String::from("no program was launched"))
+    }
+
+    fn request(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": true,
+                "supportsSetVariable": true,
+                "supportsEvaluateForHovers": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => {
+                let program = args["program"].as_str().ok_or("launch needs a program")?;
+                self.debugger = Some((self.launcher)(program)?);
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                self.event("initialized", json!({}));
+                Ok(json!({}))
+            },
+            "setBreakpoints" => self.set_breakpoints(args),
+            "configurationDone" => {
+                self.debugger()?;
+                if self.stop_on_entry {
+                    self.stopped(Stop::Stepped, "entry");
+                } else {
+                    self.running = true;
+                }
+                Ok(json!({}))
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [{ "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false }],
+            })),
+            "variables" => match args["variablesReference"].as_u64() {
+                Some(REGISTERS_REFERENCE) => Ok(json!({ "variables": self.registers()? })),
+                _ => Ok(json!({ "variables": [] })),
+            },
+            "setVariable" => {
+                let name = args["name"].as_str().unwrap_or_default();
+                let value = args["value"].as_str().unwrap_or_default();
+                self.set_register(name, value)?;
+                Ok(json!({ "value": self.evaluate(name)?.0 }))
+            },
+            "evaluate" => {
+                let (result, address) = self.evaluate(args["expression"].as_str().unwrap_or_default())?;
+                Ok(json!({ "result": result, "variablesReference": 0, "memoryReference": address }))
+            },
+            "continue" => {
+                self.debugger()?;
+                self.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    self.stopped(Stop::Stepped, "pause");
+                }
+                Ok(json!({}))
+            },
+            // There are no frames to step in or out of
+            "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" => {
+                let debugger = self.debugger()?;
+                let stop = match command {
+                    "next" => debugger.step_over(),
+                    "stepBack" => debugger.step_back(1),
+                    "reverseContinue" => debugger.reverse_cont(),
+                    _ => debugger.step(),
+                };
+                self.running = false;
+                self.stopped(stop, "step");
+                Ok(json!({}))
+            },
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "disconnect" => {
+                self.done = true;
+                Ok(json!({}))
+            },
+            "terminate" => {
+                self.done = true;
+                self.event("terminated", json!({}));
+                Ok(json!({}))
+            },
+            command => Err(format!("{} isn't supported", command)),
+        }
+    }
+
+    fn run_chunk(&mut self) -> io::Result<()> {
+        let Some(debugger) = self.debugger.as_mut() else {
+            self.running = false;
+            return Ok(());
+        };
+
+        let stop = debugger.cont_for(RUN_CHUNK);
+        if stop != Stop::Stepped {
+            self.running = false;
+            self.stopped(stop, "step");
+        }
+        self.forward_output();
+        self.flush_events()
+    }
+
+    // `reason` is for `Stop::Stepped`
+    fn stopped(&mut self, stop: Stop, reason: &str) {
+        self.forward_output();
+
+        let (reason, description) = match stop {
+            Stop::Halted => {
+                let code = self.debugger.as_ref().and_then(|d| d.computer.exit_code).unwrap_or(0);
+                self.event("exited", json!({ "exitCode": code }));
+                self.event("terminated", json!({}));
+                return;
+            },
+            Stop::Stepped => (reason, None),
+            Stop::Breakpoint(_) => ("breakpoint", None),
+            Stop::Watchpoint(_) => ("data breakpoint", None),
+            Stop::Fault(fault) => ("exception", Some(fault.to_string())),
+            Stop::HistoryStart => ("step", Some(String::from("reached the start of the history"))),
+        };
+
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(description) = description {
+            body["description"] = json!(description);
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body);
+    }
+
+    // The file name the assembler used for `path`
+    fn symbol_file(&mut self, path: &PathBuf) -> Result<Option<String>, String> {
+        let debugger = self.debugger()?;
+        let file = debugger.symbols.lines.values()
+            .map(|source| &source.file)
+            .find(|file| fs::canonicalize(file).is_ok_and(|file| &file == path));
+
+        Ok(file.cloned())
+    }
+
+    // Replaces the breakpoints of a source file
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().ok_or("setBreakpoints needs a source path")?;
+        let path = fs::canonicalize(path).map_err(|e| format!("couldn't find {}: {}", path, e))?;
+        let file = self.symbol_file(&path)?;
+        let old = self.breakpoints.remove(&path).unwrap_or_default();
+        let debugger = self.debugger()?;
+
+        for addr in old {
+            debugger.remove_breakpoint(addr);
+        }
+
+        let mut set = Vec::new();
+        let mut results = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            let found = file.as_ref().and_then(|file| debugger.symbols.address_of_line(file, line));
+
+            match found {
+                Some((addr, line)) => {
+                    debugger.add_breakpoint(addr);
+                    set.push(addr);
+                    results.push(json!({ "verified": true, "line": line, "instructionReference": addr.to_string() }));
+                },
+                None => results.push(json!({ "verified": false, "line": line, "message": "no code at or after this line" })),
+            }
+        }
+
+        self.breakpoints.insert(path, set);
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    fn stack_trace(&mut self) -> Result<Value, String> {
+        let debugger = self.debugger()?;
+        let ip = debugger.computer.ip;
+
+        let mut frame = json!({
+            "id": 0,
+            "name": debugger.symbols.describe(ip),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": ip.to_string(),
+        });
+        if let Some(source) = debugger.symbols.line_at(ip) {
+            let path = fs::canonicalize(&source.file).map_or_else(|_| source.file.clone(), |p| p.display().to_string());
+            frame["source"] = json!({ "name": source.file, "path": path });
+            frame["line"] = json!(source.line);
+            frame["column"] = json!(1);
+        }
+
+        Ok(json!({ "stackFrames": [frame], "totalFrames": 1 }))
+    }
+
+    fn registers(&mut self) -> Result<Vec<Value>, String> {
+        let debugger = self.debugger()?;
+        let comp = &debugger.computer;
+
+        let mut variables: Vec<Value> = comp.common_registers.iter().enumerate()
+            .map(|(i, value)| json!({
+                "name": format!("r{}", i),
+                "value": value.to_string(),
+                "variablesReference": 0,
+                "memoryReference": value.to_string(),
+            }))
+            .collect();
+        variables.push(json!({
+            "name": "ip",
+            "value": debugger.symbols.describe(comp.ip),
+            "variablesReference": 0,
+            "memoryReference": comp.ip.to_string(),
+        }));
+        variables.push(json!({ "name": "flags", "value": flags_name(comp.flags), "variablesReference": 0 }));
+        variables.push(json!({ "name": "mode", "value": format!("{:?}", comp.mode).to_lowercase(), "variablesReference": 0 }));
+
+        Ok(variables)
+    }
+
+    fn register(&mut self, name: &str) -> Result<Option<usize>, String> {
+        let count = self.debugger()?.computer.common_registers.len();
+        Ok(name.strip_prefix('r').and_then(|n| n.parse().ok()).filter(|&reg| reg < count))
+    }
+
+    fn set_register(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let reg = self.register(name)?;
+        let debugger = self.debugger()?;
+
+        match (reg, name) {
+            (Some(reg), _) => {
+                let value = value.parse().map_err(|_| format!("invalid value {}", value))?;
+                debugger.computer.set_register(reg, value).map_err(|e| e.to_string())?;
+            },
+            (None, "ip") => debugger.computer.ip = debugger.resolve(value)?,
+            (None, "flags") => debugger.computer.flags = parse_flags(value)?,
+            _ => return Err(format!("{} can't be set", name)),
+        }
+
+        Ok(())
+    }
+
+    // The value and the address it refers to: registers, ip, flags, labels and numbers
+    fn evaluate(&mut self, expression: &str) -> Result<(String, Option<String>), String> {
+        let expression = expression.trim();
+        let reg = self.register(expression)?;
+        let debugger = self.debugger()?;
+        let comp = &debugger.computer;
+
+        let value = match (reg, expression) {
+            (Some(reg), _) => comp.common_registers[reg],
+            (None, "ip") => comp.ip,
+            (None, "flags") => return Ok((flags_name(comp.flags).to_string(), None)),
+            (None, location) => debugger.resolve(location)?,
+        };
+
+        Ok((debugger.symbols.describe(value), Some(value.to_string())))
+    }
+
+    fn memory_address(args: &Value) -> Result<u16, String> {
+        let base: i64 = args["memoryReference"].as_str()
+            .and_then(|r| r.parse().ok())
+            .ok_or("invalid memory reference")?;
+        let addr = base + args["offset"].as_i64().unwrap_or(0);
+
+        u16::try_from(addr).map_err(|_| format!("address {} is out of range", addr))
+    }
+
+    // Stops at the first byte that can't be read
+    fn read_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = Self::memory_address(args)?;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let count = count.min(ADDRESS_SPACE_SIZE - addr as usize);
+
+        let bytes = self.debugger()?.peek_bytes(addr, count);
+        Ok(json!({
+            "address": addr.to_string(),
+            "data": base64_encode(&bytes),
+            "unreadableBytes": count - bytes.len(),
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let addr = Self::memory_address(args)?;
+        let data = args["data"].as_str().and_then(base64_decode).ok_or("invalid data")?;
+        let comp = &mut self.debugger()?.computer;
+
+        for (i, byte) in data.iter().enumerate() {
+            let at = addr.checked_add(i as u16).ok_or("the data doesn't fit into the address space")?;
+            comp.poke_byte(at, *byte).map_err(|e| e.to_string())?;
+        }
+
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use crate::assembler::Assembler;
+    use crate::computer::Engine;
+    use crate::test_util::{machine, source_file};
+
+    use super::super::tests::{CHUNK_BOUNDARY, CHUNK};
+    use super::*;
+
+    fn launcher() -> Launcher {
+        Box::new(|path| {
+            let (program, symbols) = Assembler::new().assemble_with_symbols(path)?;
+            let (comp, _) = machine(&program, Engine::default());
+            Ok(Debugger::new(comp, symbols))
+        })
+    }
+
+    fn request(seq: u64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    fn frame(message: &Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    // Everything the server sent, checking its framing
+    fn messages(output: &[u8]) -> Vec<Value> {
+        let mut output = Cursor::new(output);
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        assert_eq!(output.position() as usize, output.get_ref().len());
+        messages
+    }
+
+    fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        let response = messages.iter()
+            .find(|m| m["type"] == "response" && m["command"] == command)
+            .unwrap_or_else(|| panic!("no response to {}", command));
+        assert_eq!(response["success"], true, "{}", response);
+        &response["body"]
+    }
+
+    // A server that launched the program at `path`, stopped on entry
+    fn launched(path: &Path) -> DapServer<Vec<u8>> {
+        let mut server = DapServer::new(launcher(), None, Vec::new());
+        server.handle(&request(1, "launch", json!({ "program": path, "stopOnEntry": true }))).unwrap();
+        server
+    }
+
+    #[test]
+    fn base64() {
+        for bytes in [&b""[..], b"a", b"ab", b"abc", b"\x00\xff\x10\x80"] {
+            assert_eq!(base64_decode(&base64_encode(bytes)).as_deref(), Some(bytes));
+        }
+        assert_eq!(base64_encode(b"abcd"), "YWJjZA==");
+        assert_eq!(base64_decode("YWJjZA=="), Some(b"abcd".to_vec()));
+        assert_eq!(base64_decode("Y"), None);
+        assert_eq!(base64_decode("YW*j"), None);
+    }
+
+    #[test]
+    fn content_length_framing() {
+        let input: String = [
+            request(1, "initialize", json!({})),
+            request(2, "threads", json!({})),
+            request(3, "disconnect", json!({})),
+        ].iter().map(frame).collect();
+
+        let mut server = DapServer::new(launcher(), None, Vec::new());
+        server.run(Cursor::new(input.into_bytes())).unwrap();
+
+        let messages = messages(&server.out);
+        let seqs: Vec<_> = messages.iter().map(|m| m["request_seq"].as_u64().unwrap()).collect();
+        assert_eq!(seqs, [1, 2, 3]);
+        assert_eq!(response(&messages, "initialize")["supportsReadMemoryRequest"], true);
+        assert_eq!(response(&messages, "threads")["threads"][0]["id"], THREAD_ID);
+
+        // Other headers are ignored
+        let mut input = Cursor::new(b"Content-Type: x\r\nContent-Length: 2\r\n\r\n{}".to_vec());
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn read_memory() {
+        let path = source_file("dap-memory", CHUNK_BOUNDARY);
+        let mut server = launched(&path);
+        server.handle(&request(2, "readMemory", json!({ "memoryReference": "2", "offset": -2, "count": 3 }))).unwrap();
+        server.handle(&request(3, "readMemory", json!({ "memoryReference": "250", "count": 10 }))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let program = server.debugger.as_ref().unwrap().peek_bytes(0, 3);
+        let messages = messages(&server.out);
+        let reads: Vec<_> = messages.iter().filter(|m| m["command"] == "readMemory").collect();
+        assert_eq!(reads[0]["body"]["address"], "0");
+        assert_eq!(reads[0]["body"]["data"], base64_encode(&program));
+        assert_eq!(reads[0]["body"]["unreadableBytes"], 0);
+
+        // Past the end of the 256 bytes of memory
+        assert_eq!(base64_decode(reads[1]["body"]["data"].as_str().unwrap()).unwrap().len(), 6);
+        assert_eq!(reads[1]["body"]["unreadableBytes"], 4);
+    }
+
+    #[test]
+    fn breakpoints_by_line() {
+        let path = source_file("dap-lines", CHUNK_BOUNDARY);
+        let mut server = launched(&path);
+        let breakpoints = json!({
+            "source": { "path": path },
+            "breakpoints": [{ "line": 6 }, { "line": 11 }, { "line": 12 }],
+        });
+        server.handle(&request(2, "setBreakpoints", breakpoints)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let debugger = server.debugger.as_ref().unwrap();
+        let at = |label| debugger.resolve(label).unwrap().to_string();
+        let messages = messages(&server.out);
+        let results = &response(&messages, "setBreakpoints")["breakpoints"];
+
+        // The line of a label moves to the instruction after it
+        assert_eq!(results[0], json!({ "verified": true, "line": 7, "instructionReference": at("@loop") }));
+        assert_eq!(results[1], json!({ "verified": true, "line": 11, "instructionReference": at("@done") }));
+        assert_eq!(results[2]["verified"], false);
+    }
+
+    #[test]
+    fn continue_stops_at_a_breakpoint_line() {
+        let path = source_file("dap-continue", CHUNK_BOUNDARY);
+        let mut server = launched(&path);
+        let breakpoints = json!({ "source": { "path": path }, "breakpoints": [{ "line": 10 }] });
+        server.handle(&request(2, "setBreakpoints", breakpoints)).unwrap();
+        server.handle(&request(3, "configurationDone", json!({}))).unwrap();
+        server.handle(&request(4, "continue", json!({}))).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // @done is reached on a chunk boundary
+        while server.running {
+            server.run_chunk().unwrap();
+        }
+
+        let debugger = server.debugger.as_ref().unwrap();
+        assert_eq!(debugger.computer.ip, debugger.resolve("@done").unwrap());
+        assert_eq!(debugger.computer.instructions_retired, CHUNK as u64);
+
+        let messages = messages(&server.out);
+        let stopped = messages.iter().rfind(|m| m["event"] == "stopped").unwrap();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+        assert!(!messages.iter().any(|m| m["event"] == "exited"));
+    }
+}
@@ -0,0 +1,679 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use crate::computer::{Fault, Permissions, WatchHit, Watchpoint};
+use crate::devices::HostInput;
+use crate::hex;
+
+use super::{Debugger, Stop};
+
+// Instructions run between checks for an interrupt from the client
+const INTERRUPT_CHECK_INTERVAL: usize = 4096;
+const PACKET_SIZE: usize = 4096;
+const INTERRUPT: u8 = 0x03;
+
+// GDB's own signal numbers, not the host's
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGBUS: u8 = 10;
+const SIGSEGV: u8 = 11;
+
+/// A stream the client talks over.
+pub trait Connection: Read + Write {
+    // Whether the client asked to stop the running program, must not block
+    fn interrupted(&mut self) -> bool { false }
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> bool {
+        let mut byte = [0];
+        if self.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let read = self.read(&mut byte);
+        let _ = self.set_nonblocking(false);
+
+        // While the program runs the client sends nothing else
+        matches!(read, Ok(1)) && byte[0] == INTERRUPT
+    }
+}
+
+// Host stdin and stdout, the program's console shouldn't use them then
+pub struct StdioConnection;
+
+impl Read for StdioConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        HostInput.read(buf)
+    }
+}
+
+impl Write for StdioConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl Connection for StdioConnection {
+    fn interrupted(&mut self) -> bool {
+        HostInput.try_read_byte() == Some(INTERRUPT)
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+// The checksum covers the escaped data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = data.iter();
+    let mut ret = Vec::with_capacity(data.len());
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => ret.extend(bytes.next().map(|b| b ^ 0x20)),
+            _ => ret.push(byte),
+        }
+    }
+    ret
+}
+
+fn parse_hex<T: TryFrom<u64>>(s: &str) -> Option<T> {
+    u64::from_str_radix(s, 16).ok()?.try_into().ok()
+}
+
+// Flags are a signed register: -1 less, 0 equal, 1 greater
+fn flags_value(flags: Ordering) -> u16 {
+    flags as i8 as i16 as u16
+}
+
+fn flags_from(value: u16) -> Ordering {
+    (value as i16).cmp(&0)
+}
+
+fn signal(fault: &Fault) -> u8 {
+    match fault {
+        Fault::DivideByZero => SIGFPE,
+        Fault::Bus(_) => SIGBUS,
+        Fault::Protection(..) | Fault::PageFault(..) => SIGSEGV,
+        _ => SIGILL,
+    }
+}
+
+/// Serves the GDB remote serial protocol for a `Debugger`, one client at a time.
+///
+/// Registers are r0 and up, then `ip` and the flags, 16-bit little endian.
+/// Supports reading and writing registers and memory, stepping, continuing, software breakpoints
+/// and watchpoints, and describes the registers with a target description.
+pub struct GdbStub<C: Connection> {
+    pub debugger: Debugger,
+    conn: C,
+    acks: bool,
+    watchpoints: HashMap<(char, u16, u16), usize>, // by type, address and length
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(debugger: Debugger, conn: C) -> Self {
+        Self { debugger, conn, acks: true, watchpoints: HashMap::new() }
+    }
+
+    // Serves packets until the client kills the program, detaches or disconnects
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match self.handle(&packet) {
+                Some(reply) => self.send(&reply)?,
+                None => return Ok(()),
+            }
+            if packet == "QStartNoAckMode" {
+                self.acks = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.conn.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // The next packet, or None once the client is gone
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {},
+                // Acks, and interrupts while the program is stopped anyway
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut sum = [0; 2];
+            for digit in &mut sum {
+                let Some(byte) = self.read_byte()? else {
+                    return Ok(None);
+                };
+                *digit = byte;
+            }
+            let valid = std::str::from_utf8(&sum).ok().and_then(parse_hex::<u8>) == Some(checksum(&data));
+
+            if self.acks {
+                self.conn.write_all(if valid { b"+" } else { b"-" })?;
+                self.conn.flush()?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&unescape(&data)).into_owned()));
+            }
+        }
+    }
+
+    // Characters the protocol reserves are escaped, hex replies never have any
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        let mut data = Vec::new();
+        for byte in reply.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => data.extend([b'}', byte ^ 0x20]),
+                _ => data.push(byte),
+            }
+        }
+
+        let mut packet = vec![b'$'];
+        packet.extend(&data);
+        packet.extend(format!("#{:02x}", checksum(&data)).bytes());
+
+        loop {
+            self.conn.write_all(&packet)?;
+            self.conn.flush()?;
+            if !self.acks {
+                return Ok(());
+            }
+
+            // Resent until it's acknowledged
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // The reply, None to end the session
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => hex::encode(&self.registers()),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" | "c" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.debugger.computer.ip = addr;
+                } else if !args.is_empty() {
+                    return Some(String::from("E01"));
+                }
+
+                match if command == "s" { Some(self.debugger.step()) } else { self.cont() } {
+                    Some(stop) => self.stop_reply(stop),
+                    None => format!("S{:02x}", SIGINT),
+                }
+            },
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => String::from("OK"), // there's only one thread
+            "T" => String::from("OK"),
+            "q" | "Q" => self.query(packet),
+            "D" => {
+                let _ = self.send("OK");
+                return None;
+            },
+            "k" => return None,
+            _ => String::new(), // unsupported
+        };
+
+        Some(reply)
+    }
+
+    // None if the client interrupted it
+    fn cont(&mut self) -> Option<Stop> {
+        loop {
+            match self.debugger.cont_for(INTERRUPT_CHECK_INTERVAL) {
+                Stop::Stepped if self.conn.interrupted() => return None,
+                Stop::Stepped => continue,
+                stop => return Some(stop),
+            }
+        }
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Stepped => format!("S{:02x}", SIGTRAP),
+            Stop::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Halted => format!("W{:02x}", self.debugger.computer.exit_code.unwrap_or(0) as u8),
+            Stop::Watchpoint(WatchHit::Memory { id, addr, .. }) => {
+                let kind = self.watchpoints.iter().find(|(_, watch_id)| **watch_id == id).map(|((kind, ..), _)| *kind);
+                match kind {
+                    Some('2') => format!("T{:02x}watch:{:x};", SIGTRAP, addr),
+                    Some('3') => format!("T{:02x}rwatch:{:x};", SIGTRAP, addr),
+                    Some(_) => format!("T{:02x}awatch:{:x};", SIGTRAP, addr),
+                    None => format!("S{:02x}", SIGTRAP),
+                }
+            },
+            Stop::Watchpoint(WatchHit::Register { .. }) => format!("S{:02x}", SIGTRAP),
+            Stop::Fault(fault) => format!("S{:02x}", signal(&fault)),
+            Stop::HistoryStart => format!("S{:02x}", SIGINT),
+        }
+    }
+
+    fn register_count(&self) -> usize {
+        self.debugger.computer.common_registers.len() + 2
+    }
+
+    fn register(&self, n: usize) -> Option<u16> {
+        let comp = &self.debugger.computer;
+        let count = comp.common_registers.len();
+
+        match n {
+            n if n < count => Some(comp.common_registers[n]),
+            n if n == count => Some(comp.ip),
+            n if n == count + 1 => Some(flags_value(comp.flags)),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, n: usize, value: u16) -> bool {
+        let comp = &mut self.debugger.computer;
+        let count = comp.common_registers.len();
+
+        match n {
+            n if n < count => return comp.set_register(n, value).is_ok(),
+            n if n == count => comp.ip = value,
+            n if n == count + 1 => comp.flags = flags_from(value),
+            _ => return false,
+        }
+        true
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..self.register_count())
+            .flat_map(|n| self.register(n).unwrap_or(0).to_le_bytes())
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        match hex::decode(args).ok() {
+            Some(bytes) if bytes.len() == self.register_count() * 2 => {
+                for (n, value) in bytes.chunks(2).enumerate() {
+                    self.set_register(n, u16::from_le_bytes([value[0], value[1]]));
+                }
+                String::from("OK")
+            },
+            _ => String::from("E01"),
+        }
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        match parse_hex(args).and_then(|n| self.register(n)) {
+            Some(value) => hex::encode(&value.to_le_bytes()),
+            None => String::from("E01"),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(n, value)| Some((parse_hex(n)?, hex::decode(value).ok()?)));
+        match parsed {
+            Some((n, value)) if value.len() == 2 && self.set_register(n, u16::from_le_bytes([value[0], value[1]])) => {
+                String::from("OK")
+            },
+            _ => String::from("E01"),
+        }
+    }
+
+    // ADDR,LENGTH
+    fn parse_range(args: &str) -> Option<(u16, usize)> {
+        let (addr, len) = args.split_once(',')?;
+        Some((parse_hex(addr)?, parse_hex(len)?))
+    }
+
+    // Stops at the first byte that can't be read
+    fn read_memory(&self, args: &str) -> String {
+        let Some((addr, len)) = Self::parse_range(args) else {
+            return String::from("E01");
+        };
+
+        match self.debugger.peek_bytes(addr, len.min(PACKET_SIZE / 2)) {
+            bytes if bytes.is_empty() && len > 0 => String::from("E01"),
+            bytes => hex::encode(&bytes),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| Some((Self::parse_range(range)?, hex::decode(data).ok()?)));
+        let Some(((addr, len), data)) = parsed.filter(|((_, len), data)| *len == data.len()) else {
+            return String::from("E01");
+        };
+
+        for (i, byte) in data.into_iter().enumerate().take(len) {
+            if self.debugger.computer.poke_byte(addr.wrapping_add(i as u16), byte).is_err() {
+                return String::from("E01");
+            }
+        }
+        String::from("OK")
+    }
+
+    // TYPE,ADDR,KIND: 0 software breakpoints, 2 write, 3 read and 4 access watchpoints
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next();
+        let addr = parts.next().and_then(parse_hex::<u16>);
+        let len = parts.next().and_then(parse_hex::<u16>);
+        let (Some(kind), Some(addr), Some(len)) = (kind, addr, len) else {
+            return String::from("E01");
+        };
+
+        let accesses = match kind {
+            "0" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return String::from("OK");
+            },
+            "2" => Permissions::WRITE,
+            "3" => Permissions::READ,
+            "4" => Permissions::READ | Permissions::WRITE,
+            _ => return String::new(),
+        };
+
+        let key = (kind.chars().next().unwrap(), addr, len);
+        if insert {
+            let Some(end) = addr.checked_add(len.max(1) - 1) else {
+                return String::from("E01");
+            };
+            match self.debugger.computer.watch(Watchpoint::Memory { range: addr..=end, accesses }) {
+                Ok(id) => self.watchpoints.insert(key, id),
+                Err(_) => return String::from("E01"),
+            };
+        } else if let Some(id) = self.watchpoints.remove(&key) {
+            self.debugger.computer.unwatch(id);
+        }
+
+        String::from("OK")
+    }
+
+    fn query(&self, packet: &str) -> String {
+        let (name, args) = packet.split_once(':').unwrap_or((packet, ""));
+
+        match name {
+            "qSupported" => format!("PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+", PACKET_SIZE),
+            "QStartNoAckMode" => String::from("OK"), // acknowledged one last time
+            "qXfer" => match args.strip_prefix("features:read:target.xml:") {
+                Some(range) => match Self::parse_range(range) {
+                    Some((offset, len)) => {
+                        let xml = self.target_description();
+                        let start = (offset as usize).min(xml.len());
+                        let end = start.saturating_add(len).min(xml.len());
+                        let more = if end < xml.len() { "m" } else { "l" };
+                        format!("{}{}", more, &xml[start..end])
+                    },
+                    None => String::from("E01"),
+                },
+                None => String::new(),
+            },
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ => String::new(),
+        }
+    }
+
+    fn target_description(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\"?>\n",
+            "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n",
+            "<target version=\"1.0\">\n",
+            "  <feature name=\"org.rustrone.core\">\n",
+        ));
+
+        for n in 0..self.debugger.computer.common_registers.len() {
+            xml += &format!("    <reg name=\"r{}\" bitsize=\"16\" type=\"uint16\" regnum=\"{}\"/>\n", n, n);
+        }
+        xml += "    <reg name=\"ip\" bitsize=\"16\" type=\"code_ptr\"/>\n";
+        xml += "    <reg name=\"flags\" bitsize=\"16\" type=\"int16\"/>\n";
+        xml += "  </feature>\n</target>\n";
+
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::computer::Engine;
+    use crate::test_util::{assemble, machine};
+
+    use super::super::tests::{CHUNK, CHUNK_BOUNDARY};
+    use super::*;
+
+    const PROGRAM: &str = "
+        put r0 7;
+        put r1 200;
+        @store;
+        str r0 r1;
+        @done;
+        halt;
+    ";
+
+    // The client's side, in memory
+    struct TestConnection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        interrupt: bool,
+    }
+
+    impl Read for TestConnection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for TestConnection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for TestConnection {
+        fn interrupted(&mut self) -> bool {
+            self.interrupt
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum(data.as_bytes()))
+    }
+
+    fn stub(name: &str, source: &str, input: &[u8]) -> GdbStub<TestConnection> {
+        let (program, symbols) = assemble(name, source);
+        let (comp, _) = machine(&program, Engine::default());
+        let conn = TestConnection { input: Cursor::new(input.to_vec()), output: Vec::new(), interrupt: false };
+        GdbStub::new(Debugger::new(comp, symbols), conn)
+    }
+
+    // The client acknowledges every reply
+    fn session(name: &str, source: &str, packets: &[&str]) -> (GdbStub<TestConnection>, Vec<String>) {
+        let input: String = packets.iter().map(|p| packet(p) + "+").collect();
+        let mut stub = stub(name, source, input.as_bytes());
+        stub.run().unwrap();
+        let replies = replies(&stub.conn.output);
+        (stub, replies)
+    }
+
+    // Checks the framing of every reply and drops the acks
+    fn replies(output: &[u8]) -> Vec<String> {
+        let output = String::from_utf8(output.to_vec()).unwrap();
+        let mut replies = Vec::new();
+        let mut rest = output.as_str();
+        while let Some(start) = rest.find('$') {
+            assert!(rest[..start].chars().all(|c| c == '+' || c == '-'), "unexpected {:?}", &rest[..start]);
+            let end = start + rest[start..].find('#').unwrap();
+            let data = &rest[start + 1..end];
+            assert_eq!(parse_hex::<u8>(&rest[end + 1..end + 3]), Some(checksum(data.as_bytes())));
+
+            replies.push(String::from_utf8(unescape(data.as_bytes())).unwrap());
+            rest = &rest[end + 3..];
+        }
+        replies
+    }
+
+    #[test]
+    fn acks_and_checksums() {
+        let input = format!("$?#00{}+", packet("?"));
+        let mut stub = stub("gdb-acks", PROGRAM, input.as_bytes());
+        stub.run().unwrap();
+
+        // The corrupted packet is refused and gets no reply
+        assert!(stub.conn.output.starts_with(b"-+$S05#"));
+        assert_eq!(replies(&stub.conn.output), ["S05"]);
+    }
+
+    #[test]
+    fn no_ack_mode() {
+        let input = packet("QStartNoAckMode") + "+" + &packet("?") + &packet("qC");
+        let mut stub = stub("gdb-no-ack", PROGRAM, input.as_bytes());
+        stub.run().unwrap();
+
+        let output = String::from_utf8(stub.conn.output.clone()).unwrap();
+        assert_eq!(output, format!("+{}{}{}", packet("OK"), packet("S05"), packet("QC1")));
+    }
+
+    #[test]
+    fn escaping() {
+        let mut sender = stub("gdb-escape-send", PROGRAM, b"");
+        sender.acks = false;
+        sender.send("a$b#c}d*").unwrap();
+        let escaped = "a}\x04b}\x03c}]d}\x0a";
+        assert_eq!(sender.conn.output, format!("${}#{:02x}", escaped, checksum(escaped.as_bytes())).into_bytes());
+        assert_eq!(replies(&sender.conn.output), ["a$b#c}d*"]);
+
+        // `}P` is an escaped `p`, the checksum covers the escaped bytes
+        let escaped = "qSu}Pported";
+        let input = format!("${}#{:02x}+", escaped, checksum(escaped.as_bytes()));
+        let mut stub = stub("gdb-escape-receive", PROGRAM, input.as_bytes());
+        stub.run().unwrap();
+        assert!(replies(&stub.conn.output)[0].starts_with("PacketSize="));
+    }
+
+    #[test]
+    fn registers() {
+        let (stub, replies) = session("gdb-registers", PROGRAM, &["s", "g", "p4", "P1=3412", "p1", "P5=ffff", "g", "p9", "G00"]);
+
+        // r0 to r3, ip and the flags after the first instruction
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[1], "070000000000000003000000");
+        assert_eq!(replies[2], "0300");
+        assert_eq!(replies[3], "OK");
+        assert_eq!(replies[4], "3412");
+        assert_eq!(replies[5], "OK");
+        assert_eq!(replies[6], "07003412000000000300ffff");
+        assert_eq!(replies[7..], ["E01", "E01"]);
+        assert_eq!(stub.debugger.computer.common_registers[1], 0x1234);
+        assert_eq!(stub.debugger.computer.flags, Ordering::Less);
+    }
+
+    #[test]
+    fn memory() {
+        let (stub, replies) = session("gdb-memory", PROGRAM, &["m0,3", "Mc8,2:abcd", "mc8,2", "Mc8,2:ab", "m100,1", "m0"]);
+
+        assert_eq!(replies[0], "2c0007");
+        assert_eq!(replies[1], "OK");
+        assert_eq!(replies[2], "abcd");
+        assert_eq!(replies[3..], ["E01", "E01", "E01"]);
+        assert_eq!(stub.debugger.computer.peek_byte(201), Ok(0xcd));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (stub, replies) = session("gdb-breakpoints", PROGRAM, &["Z0,6,1", "c", "z0,6,1", "c"]);
+
+        assert_eq!(replies, ["OK", "T05swbreak:;", "OK", "W00"]);
+        assert!(stub.debugger.breakpoints().next().is_none());
+    }
+
+    #[test]
+    fn breakpoint_on_a_chunk_boundary() {
+        let (_, symbols) = assemble("gdb-chunk-boundary-symbols", CHUNK_BOUNDARY);
+        let done = symbols.address_of("@done").unwrap();
+
+        let (stub, replies) = session("gdb-chunk-boundary", CHUNK_BOUNDARY, &[&format!("Z0,{:x},1", done), "c"]);
+        assert_eq!(INTERRUPT_CHECK_INTERVAL, CHUNK);
+        assert_eq!(replies, ["OK", "T05swbreak:;"]);
+        assert_eq!(stub.debugger.computer.ip, done);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (_, replies) = session("gdb-watchpoints", PROGRAM, &["Z2,c8,1", "c", "z2,c8,1", "Z9,0,1"]);
+
+        // Reported after the store
+        assert_eq!(replies, ["OK", "T05watch:c8;", "OK", ""]);
+    }
+
+    #[test]
+    fn interrupt() {
+        let input = packet("c") + "+";
+        let mut stub = stub("gdb-interrupt", "@loop;\njmp @loop;", input.as_bytes());
+        stub.conn.interrupt = true;
+        stub.run().unwrap();
+
+        assert_eq!(replies(&stub.conn.output), ["S02"]);
+    }
+
+    #[test]
+    fn target_description_in_pages() {
+        let (stub, _) = session("gdb-target-xml", PROGRAM, &[]);
+        let xml = stub.target_description();
+
+        let mut read = String::new();
+        loop {
+            let reply = stub.query(&format!("qXfer:features:read:target.xml:{:x},40", read.len()));
+            let (more, data) = reply.split_at(1);
+            read += data;
+            if more == "l" {
+                break;
+            }
+            assert_eq!(more, "m");
+            assert_eq!(data.len(), 0x40);
+        }
+
+        assert_eq!(read, xml);
+        assert!(xml.contains("<reg name=\"r3\"") && !xml.contains("<reg name=\"r4\""));
+        assert_eq!(stub.query(&format!("qXfer:features:read:target.xml:{:x},40", xml.len() + 10)), "l");
+        assert_eq!(stub.query("qXfer:features:read:other.xml:0,40"), "");
+    }
+}
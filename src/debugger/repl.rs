@@ -0,0 +1,370 @@
+use std::io::{self, BufRead, Write};
+
+use crate::computer::{flags_name, parse_flags, Permissions, RegisterCondition, Snapshot, WatchHit, Watchpoint};
+
+use super::{Debugger, Stop};
+
+const PROMPT: &str = "(rustrone) ";
+const DISASSEMBLY_LINES: usize = 8;
+const DISASSEMBLY_CONTEXT: usize = 3; // instructions shown before ip
+
+const HELP: &str = "\
+step [N]         (s)  execute N instructions, 1 by default
+next             (n)  step over syscalls and the handlers they run
+continue         (c)  run until a breakpoint is hit or the program stops
+until LOC        (u)  run until LOC is reached
+back [N]              undo the last N instructions, 1 by default
+reverse-continue (rc) undo instructions until the previous breakpoint
+break LOC        (b)  set a breakpoint
+delete LOC       (d)  remove a breakpoint
+breakpoints           list the breakpoints
+watch rN [VALUE]      stop when a register changes, or becomes VALUE
+watch LOC [N] [PERMS] stop when N bytes at LOC are accessed, PERMS is -w- (writes) by default
+unwatch ID            remove a watchpoint
+watchpoints           list the watchpoints
+regs             (r)  show the registers
+flags                 show the flags
+print WHAT [N]   (p)  print a register, ip, flags or N bytes at LOC
+set WHAT VALUE        set a register, ip, flags (less, equal or greater) or the byte at LOC
+disas [LOC] [N]       disassemble N instructions at LOC, around ip by default
+save FILE             save the machine state to FILE
+load FILE             restore the machine state from FILE, the history is dropped
+quit             (q)
+LOC is an address or a label like @loop, an empty line repeats the last command";
+
+/// A command line interface to a `Debugger`.
+pub struct Repl<W: Write> {
+    pub debugger: Debugger,
+    out: W,
+    last_command: String,
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    s.parse().map_err(|_| format!("invalid value {}", s))
+}
+
+impl<W: Write> Repl<W> {
+    pub fn new(debugger: Debugger, out: W) -> Self {
+        Self { debugger, out, last_command: String::new() }
+    }
+
+    // Reads commands until `quit` or the end of the input
+    pub fn run(&mut self, input: impl BufRead) -> io::Result<()> {
+        self.show_location()?;
+
+        let mut lines = input.lines();
+        loop {
+            write!(self.out, "{}", PROMPT)?;
+            self.out.flush()?;
+
+            let Some(line) = lines.next() else {
+                writeln!(self.out)?;
+                return Ok(());
+            };
+            let line = line?;
+
+            let command = match line.trim() {
+                "" => self.last_command.clone(),
+                command => command.to_string(),
+            };
+            self.last_command = command.clone();
+
+            let words: Vec<&str> = command.split_whitespace().collect();
+            match words.first() {
+                Some(&"quit") | Some(&"q") => return Ok(()),
+                Some(_) => {
+                    if let Err(e) = self.execute(&words) {
+                        writeln!(self.out, "error: {}", e)?;
+                    }
+                },
+                None => {},
+            }
+        }
+    }
+
+    fn execute(&mut self, words: &[&str]) -> Result<(), String> {
+        let args = &words[1..];
+        let arg = |i: usize| args.get(i).copied().ok_or_else(|| format!("{} needs more arguments", words[0]));
+
+        match words[0] {
+            "step" | "s" => {
+                let count = match args.first() {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count {}", n))?,
+                    None => 1,
+                };
+
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.debugger.step();
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.show_stop(stop)
+            },
+            "next" | "n" => {
+                let stop = self.debugger.step_over();
+                self.show_stop(stop)
+            },
+            "continue" | "c" => {
+                let stop = self.debugger.cont();
+                self.show_stop(stop)
+            },
+            "back" => {
+                let count = match args.first() {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count {}", n))?,
+                    None => 1,
+                };
+                let stop = self.debugger.step_back(count);
+                self.show_stop(stop)
+            },
+            "reverse-continue" | "rc" => {
+                let stop = self.debugger.reverse_cont();
+                self.show_stop(stop)
+            },
+            "until" | "u" => {
+                let addr = self.debugger.resolve(arg(0)?)?;
+                let stop = self.debugger.run_to(addr);
+                self.show_stop(stop)
+            },
+            "break" | "b" => {
+                let addr = self.debugger.resolve(arg(0)?)?;
+                if !self.debugger.add_breakpoint(addr) {
+                    return Err(format!("there already is a breakpoint at {}", addr));
+                }
+                self.print(format!("breakpoint at {}", self.debugger.symbols.describe(addr)))
+            },
+            "delete" | "d" => {
+                let addr = self.debugger.resolve(arg(0)?)?;
+                if !self.debugger.remove_breakpoint(addr) {
+                    return Err(format!("there is no breakpoint at {}", addr));
+                }
+                Ok(())
+            },
+            "breakpoints" => {
+                let lines: Vec<String> = self.debugger.breakpoints()
+                    .map(|addr| self.debugger.symbols.describe(addr))
+                    .collect();
+                for line in lines {
+                    self.print(line)?;
+                }
+                Ok(())
+            },
+            "watch" => {
+                let watchpoint = self.parse_watchpoint(arg(0)?, &args[1..])?;
+                let description = self.describe_watchpoint(&watchpoint);
+                let id = self.debugger.computer.watch(watchpoint)?;
+                self.print(format!("watchpoint {}: {}", id, description))
+            },
+            "unwatch" => {
+                let id = arg(0)?.parse().map_err(|_| String::from("unwatch needs a watchpoint id"))?;
+                if !self.debugger.computer.unwatch(id) {
+                    return Err(format!("there is no watchpoint {}", id));
+                }
+                Ok(())
+            },
+            "watchpoints" => {
+                let lines: Vec<String> = self.debugger.computer.watchpoints.iter()
+                    .map(|(id, watchpoint)| format!("{}: {}", id, self.describe_watchpoint(watchpoint)))
+                    .collect();
+                for line in lines {
+                    self.print(line)?;
+                }
+                Ok(())
+            },
+            "regs" | "r" => self.show_registers(),
+            "flags" => self.print(flags_name(self.debugger.computer.flags).to_string()),
+            "print" | "p" => self.print_value(arg(0)?, args.get(1).copied()),
+            "set" => self.set_value(arg(0)?, arg(1)?),
+            "disas" => {
+                let ip = self.debugger.computer.ip;
+                let start = match args.first() {
+                    Some(loc) => self.debugger.resolve(loc)?,
+                    None => self.debugger.instructions_before(ip, DISASSEMBLY_CONTEXT),
+                };
+                let count = match args.get(1) {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count {}", n))?,
+                    None => DISASSEMBLY_LINES,
+                };
+                self.show_disassembly(start, count)
+            },
+            "save" => {
+                let path = arg(0)?;
+                self.debugger.computer.snapshot().save(path).map_err(|e| e.to_string())?;
+                self.print(format!("saved to {}", path))
+            },
+            "load" => {
+                let snapshot = Snapshot::load(arg(0)?).map_err(|e| e.to_string())?;
+                self.debugger.computer.restore_snapshot(&snapshot).map_err(|e| e.to_string())?;
+                self.show_location().map_err(|e| e.to_string())
+            },
+            "help" | "h" => self.print(HELP.to_string()),
+            command => Err(format!("unknown command {}, try help", command)),
+        }
+    }
+
+    fn print(&mut self, s: String) -> Result<(), String> {
+        writeln!(self.out, "{}", s).map_err(|e| e.to_string())
+    }
+
+    // A register number if `name` is one that exists
+    fn register(&self, name: &str) -> Option<usize> {
+        let reg: usize = name.strip_prefix('r')?.parse().ok()?;
+        (reg < self.debugger.computer.common_registers.len()).then_some(reg)
+    }
+
+    fn parse_watchpoint(&self, what: &str, args: &[&str]) -> Result<Watchpoint, String> {
+        if let Some(reg) = self.register(what) {
+            let condition = match args.first() {
+                Some(value) => RegisterCondition::Equals(parse_value(value)?),
+                None => RegisterCondition::Changes,
+            };
+            return Ok(Watchpoint::Register { reg, condition });
+        }
+
+        let start = self.debugger.resolve(what)?;
+        let mut size: u16 = 1;
+        let mut accesses = Permissions::WRITE;
+        for arg in args {
+            match arg.parse() {
+                Ok(n) if n > 0 => size = n,
+                _ => accesses = arg.parse()?,
+            }
+        }
+
+        let end = start.checked_add(size - 1).ok_or("the watched range doesn't fit into the address space")?;
+        Ok(Watchpoint::Memory { range: start..=end, accesses })
+    }
+
+    fn describe_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        match watchpoint {
+            Watchpoint::Register { reg, condition: RegisterCondition::Changes } => format!("r{} changes", reg),
+            Watchpoint::Register { reg, condition: RegisterCondition::Equals(value) } => format!("r{} becomes {}", reg, value),
+            Watchpoint::Memory { range, accesses } => {
+                let start = self.debugger.symbols.describe(*range.start());
+                match range.end() - range.start() {
+                    0 => format!("{} accesses to {}", accesses, start),
+                    size => format!("{} accesses to {} bytes at {}", accesses, size as usize + 1, start),
+                }
+            },
+        }
+    }
+
+    fn print_value(&mut self, what: &str, count: Option<&str>) -> Result<(), String> {
+        let comp = &self.debugger.computer;
+
+        if let Some(reg) = self.register(what) {
+            return self.print(format!("{} = {}", what, comp.common_registers[reg]));
+        }
+
+        match what {
+            "ip" => self.print(format!("ip = {}", self.debugger.symbols.describe(comp.ip))),
+            "flags" => self.print(flags_name(comp.flags).to_string()),
+            loc => {
+                let addr = self.debugger.resolve(loc)?;
+                let count = match count {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count {}", n))?,
+                    None => 1,
+                };
+
+                let bytes = self.debugger.peek_bytes(addr, count);
+                if bytes.len() < count {
+                    return Err(format!("can't read memory at {}", addr.wrapping_add(bytes.len() as u16)));
+                }
+
+                let bytes: Vec<String> = bytes.iter().map(u8::to_string).collect();
+                self.print(format!("{}: {}", self.debugger.symbols.describe(addr), bytes.join(" ")))
+            },
+        }
+    }
+
+    fn set_value(&mut self, what: &str, value: &str) -> Result<(), String> {
+        if let Some(reg) = self.register(what) {
+            return self.debugger.computer.set_register(reg, parse_value(value)?).map_err(|e| e.to_string());
+        }
+
+        match what {
+            "ip" => self.debugger.computer.ip = self.debugger.resolve(value)?,
+            "flags" => self.debugger.computer.flags = parse_flags(value)?,
+            loc => {
+                let addr = self.debugger.resolve(loc)?;
+                let byte = value.parse().map_err(|_| format!("invalid byte {}", value))?;
+                self.debugger.computer.poke_byte(addr, byte).map_err(|e| e.to_string())?;
+            },
+        }
+
+        Ok(())
+    }
+
+    fn show_registers(&mut self) -> Result<(), String> {
+        let comp = &self.debugger.computer;
+        let mut lines: Vec<String> = comp.common_registers.iter().enumerate()
+            .map(|(i, value)| format!("r{:<3} {}", i, value))
+            .collect();
+        lines.push(format!("ip   {}", self.debugger.symbols.describe(comp.ip)));
+        lines.push(format!("flags {}", flags_name(comp.flags)));
+        lines.push(format!("mode {:?}", comp.mode).to_lowercase());
+
+        for line in lines {
+            self.print(line)?;
+        }
+        Ok(())
+    }
+
+    // Marks ip with => and breakpoints with *
+    fn show_disassembly(&mut self, start: u16, count: usize) -> Result<(), String> {
+        let ip = self.debugger.computer.ip;
+        let mut lines = Vec::new();
+
+        for (addr, text) in self.debugger.disassemble(start, count) {
+            if let Some((label, 0)) = self.debugger.symbols.label_before(addr) {
+                lines.push(format!("{}:", label));
+            }
+
+            let marker = if addr == ip { "=>" } else { "" };
+            let breakpoint = if self.debugger.breakpoints.contains(&addr) { "*" } else { "" };
+            lines.push(format!("{:>2}{:>1} {:>5}  {}", marker, breakpoint, addr, text));
+        }
+
+        for line in lines {
+            self.print(line)?;
+        }
+        Ok(())
+    }
+
+    fn show_stop(&mut self, stop: Stop) -> Result<(), String> {
+        match stop {
+            Stop::Stepped => {},
+            Stop::Breakpoint(addr) => self.print(format!("breakpoint at {}", self.debugger.symbols.describe(addr)))?,
+            Stop::Halted => {
+                let code = match self.debugger.computer.exit_code {
+                    Some(code) => format!(" with exit code {}", code),
+                    None => String::new(),
+                };
+                return self.print(format!("halted{}", code));
+            },
+            Stop::Watchpoint(WatchHit::Memory { id, addr, access }) => {
+                self.print(format!("watchpoint {}: {} at {}", id, access, self.debugger.symbols.describe(addr)))?;
+            },
+            Stop::Watchpoint(WatchHit::Register { id, reg, value }) => {
+                self.print(format!("watchpoint {}: r{} = {}", id, reg, value))?;
+            },
+            Stop::Fault(fault) => self.print(format!("{}", fault))?,
+            Stop::HistoryStart => self.print(String::from("reached the start of the history"))?,
+        }
+
+        self.show_location().map_err(|e| e.to_string())
+    }
+
+    // The instruction at ip and where it came from
+    fn show_location(&mut self) -> io::Result<()> {
+        let ip = self.debugger.computer.ip;
+        let text = self.debugger.disassemble(ip, 1).pop().map_or_else(|| String::from("?"), |(_, text)| text);
+        let source = match self.debugger.symbols.line_at(ip) {
+            Some(line) => format!("  {}:{}", line.file, line.line),
+            None => String::new(),
+        };
+
+        writeln!(self.out, "{}: {}{}", self.debugger.symbols.describe(ip), text, source)
+    }
+}
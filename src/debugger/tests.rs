@@ -0,0 +1,71 @@
+use crate::computer::Engine;
+use crate::test_util::{assemble, machine};
+
+use super::{Debugger, Stop};
+
+// Reaches @done with exactly the 4096th instruction
+pub const CHUNK_BOUNDARY: &str = "
+    put r0 0;
+    put r1 31;
+    put r2 44;
+    mul r1 r2; 1364 iterations
+    @loop;
+    inc r0;
+    cmp r0 r1;
+    jne @loop;
+    @done;
+    halt;
+";
+pub const CHUNK: usize = 4096;
+
+fn debugger(name: &str, source: &str) -> Debugger {
+    let (program, symbols) = assemble(name, source);
+    let (comp, _) = machine(&program, Engine::default());
+    Debugger::new(comp, symbols)
+}
+
+#[test]
+fn breakpoint_on_a_chunk_boundary() {
+    let mut debugger = debugger("chunk-boundary", CHUNK_BOUNDARY);
+    let done = debugger.resolve("@done").unwrap();
+    debugger.add_breakpoint(done);
+
+    assert_eq!(debugger.cont_for(CHUNK), Stop::Breakpoint(done));
+    assert_eq!(debugger.computer.instructions_retired, CHUNK as u64);
+}
+
+// Like the gdb stub and the DAP server continue
+#[test]
+fn chunked_continue_stops_like_cont() {
+    let mut chunked = debugger("chunked-continue", CHUNK_BOUNDARY);
+    let mut plain = debugger("plain-continue", CHUNK_BOUNDARY);
+    for debugger in [&mut chunked, &mut plain] {
+        let done = debugger.resolve("@done").unwrap();
+        debugger.add_breakpoint(done);
+    }
+
+    let stop = loop {
+        match chunked.cont_for(CHUNK / 2) {
+            Stop::Stepped => continue,
+            stop => break stop,
+        }
+    };
+    assert_eq!(stop, plain.cont());
+    assert_eq!(chunked.computer.ip, plain.computer.ip);
+}
+
+#[test]
+fn run_to_and_step_over() {
+    let mut debugger = debugger("run-to", "
+        put r0 3;
+        sys 1;
+        @end;
+        halt;
+    ");
+    let end = debugger.resolve("@end").unwrap();
+
+    assert_eq!(debugger.step(), Stop::Stepped);
+    assert_eq!(debugger.step_over(), Stop::Stepped);
+    assert_eq!(debugger.computer.ip, end);
+    assert_eq!(debugger.cont(), Stop::Halted);
+}
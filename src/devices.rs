@@ -0,0 +1,52 @@
+pub mod ram;
+pub use ram::Ram;
+
+pub mod rom;
+pub use rom::Rom;
+
+pub mod console;
+pub use console::{ConsoleInput, ConsoleOutput, HostInput, SharedBuffer};
+
+/// A piece of hardware that can be mapped onto a bus.
+///
+/// Offsets are relative to the start of the region the device is mapped at.
+pub trait Device {
+    fn size(&self) -> usize;
+
+    fn read(&mut self, offset: u16) -> u8;
+    fn write(&mut self, offset: u16, value: u8);
+
+    // Read without side effects; devices with read-triggered behaviour should override this
+    fn peek(&self, offset: u16) -> u8;
+
+    // Called once per CPU tick
+    fn tick(&mut self) {}
+
+    // True if the contents only change through bus writes,
+    // which allows the CPU to cache decoded instructions fetched from it
+    fn cacheable(&self) -> bool { false }
+
+    // Level triggered: stays raised until the device is serviced
+    fn interrupt_pending(&self) -> bool { false }
+
+    // Everything needed to continue where the device left off, for snapshots.
+    // Host streams aren't part of it. Every state is checked before any is loaded
+    fn save_state(&self) -> Vec<u8> { Vec::new() }
+    fn check_state(&self, _state: &[u8]) -> Result<(), String> { Ok(()) }
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), String> { Ok(()) }
+}
+
+// For devices whose state is a fixed size byte array
+pub(crate) fn check_exact(len: usize, state: &[u8]) -> Result<(), String> {
+    if len != state.len() {
+        return Err(format!("expected {} bytes of state, got {}", len, state.len()));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn load_exact(target: &mut [u8], state: &[u8]) -> Result<(), String> {
+    check_exact(target.len(), state)?;
+    target.copy_from_slice(state);
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+use super::{check_exact, load_exact, Device};
+
+pub const CONSOLE_CHAR: u16 = 0;
+pub const CONSOLE_NUM_HI: u16 = 1;
+pub const CONSOLE_NUM_LO: u16 = 2;
+
+pub const CONSOLE_DATA: u16 = 0;
+pub const CONSOLE_STATUS: u16 = 1;
+pub const CONSOLE_CONTROL: u16 = 2;
+
+pub const STATUS_READY: u8 = 0b01;
+pub const STATUS_EOF: u8 = 0b10;
+
+pub const CONTROL_INTERRUPT: u8 = 0b01;
+
+// Reading stdin blocks, so it's done on a single background thread
+// shared by everything that wants host input
+pub fn host_stdin() -> &'static Mutex<Receiver<u8>> {
+    static STDIN: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+
+    STDIN.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) => if tx.send(byte).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Mutex::new(rx)
+    })
+}
+
+// Host stdin for anything besides the console, like a debugger's commands.
+// Input is shared with consoles reading stdin, so it only sees what they haven't taken
+pub struct HostInput;
+
+impl Read for HostInput {
+    // Blocks until there is a byte, then takes everything already there
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let source = host_stdin().lock().unwrap();
+        let mut read = 0;
+
+        while read < buf.len() {
+            let byte = if read == 0 { source.recv().ok() } else { source.try_recv().ok() };
+            let Some(byte) = byte else {
+                break;
+            };
+
+            buf[read] = byte;
+            read += 1;
+            if byte == b'\n' {
+                break;
+            }
+        }
+
+        Ok(read)
+    }
+}
+
+impl HostInput {
+    // Doesn't wait for input
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        host_stdin().lock().unwrap().try_recv().ok()
+    }
+}
+
+// Output captured in memory instead of being written to the host,
+// handles are cheap to clone so the embedder can keep one around
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+/// Character and number output.
+///
+/// Registers:
+/// - `CONSOLE_CHAR` - writing a byte prints it as is
+/// - `CONSOLE_NUM_HI` - latches the high byte of the next number
+/// - `CONSOLE_NUM_LO` - prints `(hi << 8) | lo` in decimal and resets the latch
+pub struct ConsoleOutput {
+    sink: Box<dyn Write>,
+    num_hi: u8,
+}
+
+impl ConsoleOutput {
+    pub fn new(sink: Box<dyn Write>) -> Self {
+        Self { sink, num_hi: 0 }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    pub fn buffered() -> (Self, SharedBuffer) {
+        let buffer = SharedBuffer::new();
+        (Self::new(Box::new(buffer.clone())), buffer)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        // The guest has no way to handle a failing host stream
+        let _ = self.sink.write_all(bytes).and_then(|_| self.sink.flush());
+    }
+}
+
+impl Device for ConsoleOutput {
+    fn size(&self) -> usize { 3 }
+
+    fn read(&mut self, offset: u16) -> u8 { self.peek(offset) }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset {
+            CONSOLE_CHAR => self.emit(&[value]),
+            CONSOLE_NUM_HI => self.num_hi = value,
+            CONSOLE_NUM_LO => {
+                let num = ((self.num_hi as u16) << 8) | value as u16;
+                self.num_hi = 0;
+                self.emit(num.to_string().as_bytes());
+            },
+            _ => {},
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset {
+            CONSOLE_NUM_HI => self.num_hi,
+            _ => 0,
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> { vec![self.num_hi] }
+
+    fn check_state(&self, state: &[u8]) -> Result<(), String> { check_exact(1, state) }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        load_exact(std::slice::from_mut(&mut self.num_hi), state)
+    }
+}
+
+/// Character input.
+///
+/// Registers:
+/// - `CONSOLE_DATA` - reading pops the next byte, 0 if there is none
+/// - `CONSOLE_STATUS` - `STATUS_READY` if a byte is available, `STATUS_EOF` once the source is exhausted
+/// - `CONSOLE_CONTROL` - set `CONTROL_INTERRUPT` to raise an interrupt while a byte is available
+pub struct ConsoleInput {
+    source: Option<&'static Mutex<Receiver<u8>>>,
+    queue: VecDeque<u8>,
+    control: u8,
+}
+
+impl ConsoleInput {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            source: None,
+            queue: VecDeque::from(bytes),
+            control: 0,
+        }
+    }
+
+    pub fn stdin() -> Self {
+        Self {
+            source: Some(host_stdin()),
+            queue: VecDeque::new(),
+            control: 0,
+        }
+    }
+
+    fn poll(&mut self) {
+        while let Some(source) = self.source {
+            let received = source.lock().unwrap().try_recv();
+            match received {
+                Ok(byte) => self.queue.push_back(byte),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.source = None,
+            }
+        }
+    }
+
+    fn status(&self) -> u8 {
+        if !self.queue.is_empty() {
+            STATUS_READY
+        } else if self.source.is_none() {
+            STATUS_EOF
+        } else {
+            0
+        }
+    }
+}
+
+impl Device for ConsoleInput {
+    fn size(&self) -> usize { 3 }
+
+    fn read(&mut self, offset: u16) -> u8 {
+        self.poll();
+
+        match offset {
+            CONSOLE_DATA => self.queue.pop_front().unwrap_or(0),
+            _ => self.peek(offset),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset == CONSOLE_CONTROL {
+            self.control = value;
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u8 {
+        match offset {
+            CONSOLE_DATA => self.queue.front().copied().unwrap_or(0),
+            CONSOLE_STATUS => self.status(),
+            CONSOLE_CONTROL => self.control,
+            _ => 0,
+        }
+    }
+
+    // Polling takes a lock, so it's only done every tick when an interrupt has to be raised
+    fn tick(&mut self) {
+        if self.control & CONTROL_INTERRUPT != 0 {
+            self.poll();
+        }
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.control & CONTROL_INTERRUPT != 0 && !self.queue.is_empty()
+    }
+
+    // The control register followed by input that wasn't read yet
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.control];
+        state.extend(&self.queue);
+        state
+    }
+
+    fn check_state(&self, state: &[u8]) -> Result<(), String> {
+        match state {
+            [] => Err(String::from("missing console input state")),
+            _ => Ok(()),
+        }
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let (control, queue) = state.split_first().ok_or("missing console input state")?;
+        self.control = *control;
+        self.queue = queue.iter().copied().collect();
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use crate::bus::ADDRESS_SPACE_SIZE;
+
+use super::{check_exact, load_exact, Device};
+
+pub struct Ram {
+    data: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        assert!(size <= ADDRESS_SPACE_SIZE, "RAM can't be larger than 64 KiB");
+
+        Self { data: vec![0; size] }
+    }
+}
+
+impl Device for Ram {
+    fn size(&self) -> usize { self.data.len() }
+
+    fn read(&mut self, offset: u16) -> u8 { self.data[offset as usize] }
+    fn write(&mut self, offset: u16, value: u8) { self.data[offset as usize] = value }
+    fn peek(&self, offset: u16) -> u8 { self.data[offset as usize] }
+    fn cacheable(&self) -> bool { true }
+
+    fn save_state(&self) -> Vec<u8> { self.data.clone() }
+    fn check_state(&self, state: &[u8]) -> Result<(), String> { check_exact(self.data.len(), state) }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> { load_exact(&mut self.data, state) }
+}
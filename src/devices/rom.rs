@@ -0,0 +1,29 @@
+use crate::bus::ADDRESS_SPACE_SIZE;
+
+use super::{check_exact, load_exact, Device};
+
+// Writes to ROM are silently ignored
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>) -> Self {
+        assert!(data.len() <= ADDRESS_SPACE_SIZE, "ROM can't be larger than 64 KiB");
+
+        Self { data }
+    }
+}
+
+impl Device for Rom {
+    fn size(&self) -> usize { self.data.len() }
+
+    fn read(&mut self, offset: u16) -> u8 { self.data[offset as usize] }
+    fn write(&mut self, _offset: u16, _value: u8) {}
+    fn peek(&self, offset: u16) -> u8 { self.data[offset as usize] }
+    fn cacheable(&self) -> bool { true }
+
+    fn save_state(&self) -> Vec<u8> { self.data.clone() }
+    fn check_state(&self, state: &[u8]) -> Result<(), String> { check_exact(self.data.len(), state) }
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> { load_exact(&mut self.data, state) }
+}
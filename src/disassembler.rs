@@ -0,0 +1,101 @@
+use std::fmt;
+
+use crate::instructions::{decode, Decoded};
+
+pub struct DisassembledInstruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+// Formatted as valid source with the address and raw bytes in a comment
+impl fmt::Display for DisassembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{:<16} {:>5}: {}", format!("{};", self.text), self.addr, bytes.join(" "))
+    }
+}
+
+// Returns the instruction text and its size, bytes that don't decode become raw data.
+// Encodings the assembler would never produce are treated as data too, so the output round-trips.
+pub fn disassemble_one(bytes: &[u8]) -> (String, usize) {
+    let first_byte = match bytes.first() {
+        Some(byte) => *byte,
+        None => return (String::new(), 0),
+    };
+
+    match decode(bytes) {
+        Some(Decoded { spec, operands }) if spec.format.encode(spec.opcode, &operands) == bytes[..spec.size()] => {
+            let mut text = String::from(spec.mnemonic);
+            for op in spec.format.format_operands(&operands) {
+                text.push(' ');
+                text.push_str(&op);
+            }
+            (text, spec.size())
+        },
+        _ => (format!("#{}", first_byte), 1),
+    }
+}
+
+pub fn disassemble(bytes: &[u8], base_addr: u16) -> Vec<DisassembledInstruction> {
+    let mut ret = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let (text, size) = disassemble_one(&bytes[offset..]);
+        ret.push(DisassembledInstruction {
+            addr: base_addr.wrapping_add(offset as u16),
+            bytes: bytes[offset..offset + size].to_vec(),
+            text,
+        });
+        offset += size;
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::assembler::Assembler;
+
+    use super::*;
+
+    fn assemble(path: &str) -> Vec<u8> {
+        Assembler::new().assemble(path).unwrap()
+    }
+
+    // The disassembly assembles back into the same bytes
+    fn round_trip(name: &str, bytes: &[u8]) {
+        let source: Vec<String> = disassemble(bytes, 0).iter().map(|instr| instr.to_string()).collect();
+
+        let path = env::temp_dir().join(format!("rustrone-{}-{}.s", process::id(), name));
+        fs::write(&path, source.join("\n")).unwrap();
+        let reassembled = assemble(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reassembled, bytes, "{}", name);
+    }
+
+    #[test]
+    fn samples() {
+        for name in ["factorial.s", "new.s", "bench.s"] {
+            round_trip(name, &assemble(&format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name)));
+        }
+    }
+
+    // Including encodings the assembler doesn't produce, like unused opcodes or stray register bits
+    #[test]
+    fn every_byte() {
+        let bytes: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+        round_trip("every-byte", &bytes);
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(disassemble_one(&[11 << 2, 0x20, 5]), (String::from("put r2 5"), 3));
+        assert_eq!(disassemble_one(&[1 << 2]), (String::from("halt"), 1));
+        assert_eq!(disassemble_one(&[15 << 2]), (String::from("#60"), 1));
+    }
+}
@@ -1,156 +1,192 @@
 pub mod operands;
-use self::operands::OperandType;
+use self::operands::{Format, OperandType, Operands};
 
 pub mod misc;
-pub use misc::{opcodes::*, structs::*};
+pub use misc::opcodes::*;
 
 pub mod basic_math;
-pub use basic_math::{opcodes::*, structs::*};
+pub use basic_math::opcodes::*;
 
 pub mod inc_dec;
-pub use inc_dec::{opcodes::*, structs::*};
+pub use inc_dec::opcodes::*;
 
 pub mod data_movement;
-pub use data_movement::{opcodes::*, structs::*};
+pub use data_movement::opcodes::*;
 
 pub mod branching;
-pub use branching::{opcodes::*, structs::*};
+pub use branching::opcodes::*;
 
+pub mod interrupts;
+pub use interrupts::{opcodes::*, system_registers::*};
 
-use std::{cmp::Ordering, collections::HashMap, vec};
+pub mod io;
+pub use io::opcodes::*;
+
+
+use std::collections::HashMap;
 use lazy_static::lazy_static;
 
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
+
+// What an instruction does, as far as the execution engines and timing models care
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kind {
+    Alu,
+    Load,
+    Store,
+    Io,
+    Jump,
+    Branch, // conditional jump
+    System, // changes the machine state beyond registers and memory
+}
 
-mod wrap {
-    pub(self) trait RegisterWrapper {
-        fn get(&self, registers: &Vec<u16>) -> u16;
-        fn set(&mut self, value: u16, registers: &mut Vec<u16>);
-    }
-    
-    pub(self) struct RegisterL8Wrapper {
-        pub id: usize,
-    }
-    impl RegisterWrapper for RegisterL8Wrapper {
-        fn get(&self, registers: &Vec<u16>) -> u16 {
-            registers[self.id] & 0x00FF
-        }
-    
-        fn set(&mut self, mut value: u16, registers: &mut Vec<u16>) {
-            value |= 0xFF00;
-            registers[self.id] &= value;
-        }
-    }
-    
-    pub(self) struct RegisterH8Wrapper {
-        pub id: usize,
-    }
-    impl RegisterWrapper for RegisterH8Wrapper {
-        fn get(&self, registers: &Vec<u16>) -> u16 {
-            registers[self.id] & 0xFF00
-        }
-    
-        fn set(&mut self, mut value: u16, registers: &mut Vec<u16>) {
-            value |= 0x00FF;
-            registers[self.id] &= value;
-        }
+impl Kind {
+    // Anything after such an instruction may not run next
+    pub fn ends_block(self) -> bool {
+        matches!(self, Kind::Jump | Kind::Branch | Kind::System)
     }
-    
-    pub(self) struct Register16Wrapper {
-        pub id: usize,
-    }
-    impl RegisterWrapper for Register16Wrapper {
-        fn get(&self, registers: &Vec<u16>) -> u16 {
-            registers[self.id]
-        }
-    
-        fn set(&mut self, value: u16, registers: &mut Vec<u16>) {
-            registers[self.id] = value;
-        }
+}
+
+// Where an instruction takes its inputs from or puts its results
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Location {
+    RegA, // the first register operand
+    RegB, // the second one
+    Reg(usize),
+    Flags,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Dataflow {
+    pub reads: &'static [Location],
+    pub writes: &'static [Location],
+}
+
+// Memory, ports and system state aren't tracked
+pub mod dataflow {
+    use super::{Dataflow, Location::*};
+
+    pub const NONE: Dataflow = Dataflow { reads: &[], writes: &[] };
+    pub const READ_A: Dataflow = Dataflow { reads: &[RegA], writes: &[] };
+    pub const READ_AB: Dataflow = Dataflow { reads: &[RegA, RegB], writes: &[] };
+    pub const WRITE_A: Dataflow = Dataflow { reads: &[], writes: &[RegA] };
+    pub const UPDATE_A: Dataflow = Dataflow { reads: &[RegA], writes: &[RegA] };
+    pub const COPY: Dataflow = Dataflow { reads: &[RegB], writes: &[RegA] };
+    pub const BINARY: Dataflow = Dataflow { reads: &[RegA, RegB], writes: &[RegA] };
+    pub const DIVIDE: Dataflow = Dataflow { reads: &[RegA, RegB], writes: &[Reg(0), Reg(1)] };
+    pub const COMPARE: Dataflow = Dataflow { reads: &[RegA, RegB], writes: &[Flags] };
+    pub const BRANCH: Dataflow = Dataflow { reads: &[Flags], writes: &[] };
+    pub const BRANCH_A: Dataflow = Dataflow { reads: &[RegA, Flags], writes: &[] };
+}
+
+/// Everything there is to know about an instruction,
+/// the assembler, decoder and disassembler are all driven by these.
+pub struct InstructionSpec {
+    pub mnemonic: &'static str,
+    pub opcode: u8,
+    pub format: Format,
+    pub kind: Kind,
+    pub privileged: bool, // faults in user mode
+    pub dataflow: Dataflow,
+    pub description: &'static str,
+    pub execute: fn(&mut Computer, Operands) -> Result<(), Fault>,
+}
+
+impl InstructionSpec {
+    pub fn size(&self) -> usize { self.format.size() }
+
+    pub fn assemble(&self, operands: &[String]) -> Result<Vec<u8>, String> {
+        Ok(self.format.encode(self.opcode, &self.format.parse(operands)?))
     }
 }
 
-pub trait Executable {
-    fn execute(&self, computer: &mut Computer, first_byte: u8);
-    fn mnemonic(&self) -> String;
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8>;
+/// Instructions that a machine variant enables or leaves out together.
+pub struct InstructionGroup {
+    pub name: &'static str,
+    pub specs: &'static [InstructionSpec],
+}
+
+// Every instruction group
+pub static ISA: &[InstructionGroup] = &[
+    InstructionGroup { name: "misc", specs: misc::SPECS },
+    InstructionGroup { name: "basic_math", specs: basic_math::SPECS },
+    InstructionGroup { name: "inc_dec", specs: inc_dec::SPECS },
+    InstructionGroup { name: "data_movement", specs: data_movement::SPECS },
+    InstructionGroup { name: "branching", specs: branching::SPECS },
+    InstructionGroup { name: "interrupts", specs: interrupts::SPECS },
+    InstructionGroup { name: "io", specs: io::SPECS },
+];
+
+pub const OPCODE_COUNT: usize = 64; // the opcode takes the high 6 bits of the first byte
+pub const MAX_INSTRUCTION_SIZE: usize = 3;
+
+/// A fully decoded instruction, ready to be executed.
+#[derive(Clone, Copy)]
+pub struct Decoded {
+    pub spec: &'static InstructionSpec,
+    pub operands: Operands,
 }
 
 lazy_static! {
-    pub static ref INSTRUCTIONS: HashMap <u8, Box<dyn Executable + Sync>> = {
-        let mut instrs = HashMap::<u8, Box<dyn Executable + Sync>>::new();
-
-        instrs.insert(NOP_OPCODE, Box::from(Nop));
-        instrs.insert(HALT_OPCODE, Box::from(Halt));
-
-        instrs.insert(ADD_OPCODE, Box::from(Add));
-        instrs.insert(SUB_OPCODE, Box::from(Sub));
-        instrs.insert(MUL_OPCODE, Box::from(Mul));
-        instrs.insert(DIV_OPCODE, Box::from(Div));
-
-        instrs.insert(INC_OPCODE, Box::from(Inc));
-        instrs.insert(DEC_OPCODE, Box::from(Dec));
-
-        instrs.insert(LDR_OPCODE, Box::from(Ldr));
-        instrs.insert(STR_OPCODE, Box::from(Str));
-        instrs.insert(PUT_OPCODE, Box::from(Put));
-        instrs.insert(MOV_OPCODE, Box::from(Mov));
-
-        instrs.insert(CMP_OPCODE, Box::from(Cmp));
-        instrs.insert(JMP_OPCODE, Box::from(Jmp));
-        instrs.insert(JL_OPCODE, Box::from(Jcond(true,  Ordering::Less)));     // jl
-        instrs.insert(JGE_OPCODE, Box::from(Jcond(false, Ordering::Less)));    // jge
-        instrs.insert(JG_OPCODE, Box::from(Jcond(true,  Ordering::Greater)));  // jg
-        instrs.insert(JLE_OPCODE, Box::from(Jcond(false, Ordering::Greater))); // jle
-        instrs.insert(JE_OPCODE, Box::from(Jcond(true,  Ordering::Equal)));    // je
-        instrs.insert(JNE_OPCODE, Box::from(Jcond(false, Ordering::Equal)));   // jne
+    // Indexed by opcode
+    pub static ref INSTRUCTIONS: [Option<&'static InstructionSpec>; OPCODE_COUNT] = {
+        let mut instrs = [None; OPCODE_COUNT];
+
+        for spec in all_instructions() {
+            let slot = &mut instrs[spec.opcode as usize];
+            assert!(slot.is_none(), "opcode {} is used twice", spec.opcode);
+            *slot = Some(spec);
+        }
 
         instrs
     };
 
-    pub static ref INSTRUCTION_SIZE: Vec <(Vec<OperandType>, u8)> = {
-        let mut sizes: Vec <(Vec<OperandType>, u8)> = Vec::new();
+    static ref MNEMONICS: HashMap<&'static str, Vec<&'static InstructionSpec>> = {
+        let mut mnemonics = HashMap::<&'static str, Vec<&'static InstructionSpec>>::new();
 
-        sizes.push((vec![OperandType::Register, OperandType::Register], 2));
-        sizes.push((vec![OperandType::Register, OperandType::Value], 3));
-        sizes.push((vec![OperandType::Register], 2));
-        sizes.push((vec![OperandType::Value], 2));
-        sizes.push((Vec::new(), 1));
+        for spec in all_instructions() {
+            mnemonics.entry(spec.mnemonic).or_default().push(spec);
+        }
 
-        sizes
+        mnemonics
     };
 }
 
-pub fn get_instruction_size(operand_types: &Vec<OperandType>) -> u8 {
-    let mut equality: bool;
-
-    for entry in (& INSTRUCTION_SIZE).iter() {
-        equality = true;
-        if operand_types.len() == entry.0.len() {
-            for op in operand_types.iter().enumerate() {
-                if * op.1 != entry.0[op.0] {
-                    equality = false;
-                    break;
-                }
-            }
-        } else {
-            equality = false;
-        }
+pub fn lookup(first_byte: u8) -> Option<&'static InstructionSpec> {
+    INSTRUCTIONS[(first_byte >> 2) as usize]
+}
 
-        if equality {
-            return entry.1;
-        }
-    }
+pub fn all_instructions() -> impl Iterator<Item = &'static InstructionSpec> {
+    ISA.iter().flat_map(|group| group.specs.iter())
+}
 
-    return 0;
+pub fn find_group(name: &str) -> Option<&'static InstructionGroup> {
+    ISA.iter().find(|group| group.name == name)
 }
 
-pub fn instr_from_str(s: &str) -> Option<&'static Box<dyn Executable + Sync + 'static>> {
-    for instr in INSTRUCTIONS.values() {
-        if instr.mnemonic() == s {
-            return Some(&instr);
-        }
+pub fn group_of(spec: &InstructionSpec) -> &'static InstructionGroup {
+    ISA.iter()
+        .find(|group| group.specs.iter().any(|s| s.opcode == spec.opcode))
+        .expect("every instruction belongs to a group")
+}
+
+// Instructions like `jne` have a form for every kind of operand they take
+pub fn find_instruction(mnemonic: &str, operand_types: &[OperandType]) -> Option<&'static InstructionSpec> {
+    MNEMONICS.get(mnemonic)?.iter()
+        .find(|spec| spec.format.operand_types() == operand_types)
+        .copied()
+}
+
+pub fn is_mnemonic(s: &str) -> bool {
+    MNEMONICS.contains_key(s)
+}
+
+// `None` if `bytes` is too short or holds an unknown opcode
+pub fn decode(bytes: &[u8]) -> Option<Decoded> {
+    let spec = lookup(*bytes.first()?)?;
+    if bytes.len() < spec.size() {
+        return None;
     }
 
-    return None
+    Some(Decoded { spec, operands: spec.format.decode(bytes) })
 }
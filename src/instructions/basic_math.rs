@@ -1,6 +1,6 @@
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
 
-use super::{Executable, operands::{get_next_reg_reg_operands, OperandType}};
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
 
 pub mod opcodes {
     pub const ADD_OPCODE: u8 = 2;
@@ -9,80 +9,45 @@ pub mod opcodes {
     pub const DIV_OPCODE: u8 = 5;
 }
 
-pub mod structs {
-    pub struct Add;
-    pub struct Sub;
-    pub struct Mul;
-    pub struct Div;
-}
-
 use opcodes::*;
-use structs::*;
-
-fn assemble_basic_math(opcode: u8, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-    vec![
-        opcode << 2,
-        (operands[0][1..].parse::<u8>().expect("Invalid register id") << 4) +
-        operands[1][1..].parse::<u8>().expect("Invalid register id"),
-    ]
-}
-
-impl Executable for Add {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.common_registers[reg1] += computer.common_registers[reg2];
-    }
 
-    fn mnemonic(&self) -> String { String::from("add") }
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "add", opcode: ADD_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::BINARY, description: "rA += rB", execute: add },
+    InstructionSpec { mnemonic: "sub", opcode: SUB_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::BINARY, description: "rA -= rB", execute: sub },
+    InstructionSpec { mnemonic: "mul", opcode: MUL_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::BINARY, description: "rA *= rB", execute: mul },
+    InstructionSpec { mnemonic: "div", opcode: DIV_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::DIVIDE, description: "r0 = rA / rB, r1 = rA % rB", execute: div },
+];
 
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_basic_math(ADD_OPCODE, operands, operand_types)
-    }
+fn add(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg1].wrapping_add(computer.common_registers[ops.reg2]);
+    Ok(())
 }
 
-impl Executable for Sub {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.common_registers[reg1] -= computer.common_registers[reg2];
-    }
-
-    fn mnemonic(&self) -> String { String::from("sub") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_basic_math(SUB_OPCODE, operands, operand_types)
-    }
+fn sub(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg1].wrapping_sub(computer.common_registers[ops.reg2]);
+    Ok(())
 }
 
-impl Executable for Mul {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.common_registers[reg1] *= computer.common_registers[reg2];
-    }
-
-    fn mnemonic(&self) -> String { String::from("mul") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_basic_math(MUL_OPCODE, operands, operand_types)
-    }
+fn mul(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg1].wrapping_mul(computer.common_registers[ops.reg2]);
+    Ok(())
 }
 
-impl Executable for Div {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        
-        let value1 = computer.common_registers[reg1];
-        let value2 = computer.common_registers[reg2];
-
-        let div = value1 / value2;
-        let rem = value1 % value2;
-
-        computer.common_registers[0] = div;
-        computer.common_registers[1] = rem;
+fn div(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    let value1 = computer.common_registers[ops.reg1];
+    let value2 = computer.common_registers[ops.reg2];
+    if value2 == 0 {
+        return Err(Fault::DivideByZero);
+    }
+    // The results always go to r0 and r1, which a one register machine doesn't have
+    if computer.common_registers.len() < 2 {
+        return Err(Fault::InvalidRegister(1));
     }
 
-    fn mnemonic(&self) -> String { String::from("div") }
+    let div = value1 / value2;
+    let rem = value1 % value2;
 
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_basic_math(DIV_OPCODE, operands, operand_types)
-    }
+    computer.common_registers[0] = div;
+    computer.common_registers[1] = rem;
+    Ok(())
 }
@@ -1,8 +1,8 @@
 use std::cmp::Ordering;
 
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
 
-use super::{Executable, operands::{get_next_reg_reg_operands, OperandType, get_next_reg_operand}};
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
 
 pub mod opcodes {
     pub const CMP_OPCODE: u8 = 12;
@@ -15,95 +15,75 @@ pub mod opcodes {
     pub const JLE_OPCODE: u8 = 18;
     pub const JE_OPCODE: u8 = 19;
     pub const JNE_OPCODE: u8 = 20;
-}
-
-pub mod structs {
-    use std::cmp::Ordering;
 
-    pub struct Cmp;
-    pub struct Jmp;
-    pub struct Jcond(pub bool, pub Ordering); // true - cond, false - ncond
+    // Jumps to an address given as a value
+    pub const JMP_VALUE_OPCODE: u8 = 30;
+    pub const JL_VALUE_OPCODE: u8 = 31;
+    pub const JGE_VALUE_OPCODE: u8 = 32;
+    pub const JG_VALUE_OPCODE: u8 = 33;
+    pub const JLE_VALUE_OPCODE: u8 = 34;
+    pub const JE_VALUE_OPCODE: u8 = 35;
+    pub const JNE_VALUE_OPCODE: u8 = 36;
 }
 
 use opcodes::*;
-use structs::*;
-
-impl Executable for Cmp {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.flags = computer.common_registers[reg1].cmp(&computer.common_registers[reg2]);
-    }
 
-    fn mnemonic(&self) -> String { String::from("cmp") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![
-            CMP_OPCODE << 2,
-            (operands[0][1..].parse::<u8>().expect("Invalid register id") << 4) +
-            operands[1][1..].parse::<u8>().expect("Invalid register id"),
-        ]
-    }
+// Conditions a jump is taken on
+const ALWAYS: u8 = 0;
+const LESS: u8 = 1;
+const GREATER_OR_EQUAL: u8 = 2;
+const GREATER: u8 = 3;
+const LESS_OR_EQUAL: u8 = 4;
+const EQUAL: u8 = 5;
+const NOT_EQUAL: u8 = 6;
+
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "cmp", opcode: CMP_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::COMPARE, description: "compare rA to rB and set the flags", execute: cmp },
+
+    InstructionSpec { mnemonic: "jmp", opcode: JMP_OPCODE, format: Format::Reg, kind: Kind::Jump, privileged: false, dataflow: dataflow::READ_A, description: "jump to rA", execute: jump_reg::<ALWAYS> },
+    InstructionSpec { mnemonic: "jl", opcode: JL_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if less", execute: jump_reg::<LESS> },
+    InstructionSpec { mnemonic: "jge", opcode: JGE_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if greater or equal", execute: jump_reg::<GREATER_OR_EQUAL> },
+    InstructionSpec { mnemonic: "jg", opcode: JG_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if greater", execute: jump_reg::<GREATER> },
+    InstructionSpec { mnemonic: "jle", opcode: JLE_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if less or equal", execute: jump_reg::<LESS_OR_EQUAL> },
+    InstructionSpec { mnemonic: "je", opcode: JE_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if equal", execute: jump_reg::<EQUAL> },
+    InstructionSpec { mnemonic: "jne", opcode: JNE_OPCODE, format: Format::Reg, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH_A, description: "jump to rA if not equal", execute: jump_reg::<NOT_EQUAL> },
+
+    InstructionSpec { mnemonic: "jmp", opcode: JMP_VALUE_OPCODE, format: Format::Value, kind: Kind::Jump, privileged: false, dataflow: dataflow::NONE, description: "jump to value", execute: jump_value::<ALWAYS> },
+    InstructionSpec { mnemonic: "jl", opcode: JL_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if less", execute: jump_value::<LESS> },
+    InstructionSpec { mnemonic: "jge", opcode: JGE_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if greater or equal", execute: jump_value::<GREATER_OR_EQUAL> },
+    InstructionSpec { mnemonic: "jg", opcode: JG_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if greater", execute: jump_value::<GREATER> },
+    InstructionSpec { mnemonic: "jle", opcode: JLE_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if less or equal", execute: jump_value::<LESS_OR_EQUAL> },
+    InstructionSpec { mnemonic: "je", opcode: JE_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if equal", execute: jump_value::<EQUAL> },
+    InstructionSpec { mnemonic: "jne", opcode: JNE_VALUE_OPCODE, format: Format::Value, kind: Kind::Branch, privileged: false, dataflow: dataflow::BRANCH, description: "jump to value if not equal", execute: jump_value::<NOT_EQUAL> },
+];
+
+fn cmp(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.flags = computer.common_registers[ops.reg1].cmp(&computer.common_registers[ops.reg2]);
+    Ok(())
 }
 
-impl Executable for Jmp {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let reg = get_next_reg_operand(computer);
-        computer.ip = computer.common_registers[reg];
-    }
-
-    fn mnemonic(&self) -> String { String::from("jmp") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![
-            JMP_OPCODE << 2,
-            operands[0][1..].parse::<u8>().expect("Invalid register id") << 4,
-        ]
+fn condition_holds(flags: Ordering, condition: u8) -> bool {
+    match condition {
+        LESS => flags == Ordering::Less,
+        GREATER_OR_EQUAL => flags != Ordering::Less,
+        GREATER => flags == Ordering::Greater,
+        LESS_OR_EQUAL => flags != Ordering::Greater,
+        EQUAL => flags == Ordering::Equal,
+        NOT_EQUAL => flags != Ordering::Equal,
+        _ => true,
     }
 }
 
-impl Executable for Jcond {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let reg = get_next_reg_operand(computer);
-        if (computer.flags == self.1) == self.0 {
-            computer.ip = computer.common_registers[reg];
-        }
+fn jump_reg<const CONDITION: u8>(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    if condition_holds(computer.flags, CONDITION) {
+        computer.jump(computer.common_registers[ops.reg1]);
     }
+    Ok(())
+}
 
-    fn mnemonic(&self) -> String {
-        let mut m = String::new();
-        m.reserve(3);
-        
-        m.push('j');
-        if ! self.0 {
-            m.push('n');
-        }
-        m.push(match self.1 {
-            Ordering::Less    => 'l',
-            Ordering::Equal   => 'e',
-            Ordering::Greater => 'g',
-        });
-
-        m
-    }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        let opcode = if self.0 {
-            match self.1 {
-                Ordering::Less    => JL_OPCODE,
-                Ordering::Equal   => JE_OPCODE,
-                Ordering::Greater => JG_OPCODE,
-            }
-        } else {
-            match self.1 {
-                Ordering::Less    => JGE_OPCODE,
-                Ordering::Equal   => JNE_OPCODE,
-                Ordering::Greater => JLE_OPCODE,
-            }
-        };
-
-        vec![
-            opcode << 2,
-            operands[0][1..].parse::<u8>().expect("Invalid register id") << 4,
-        ]
+fn jump_value<const CONDITION: u8>(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    if condition_holds(computer.flags, CONDITION) {
+        computer.jump(ops.value as u16);
     }
+    Ok(())
 }
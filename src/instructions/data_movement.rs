@@ -1,6 +1,6 @@
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
 
-use super::{operands::{OperandType, get_next_reg_reg_operands, get_next_reg_operand}, Executable};
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
 
 pub mod opcodes {
     pub const LDR_OPCODE: u8 = 8;
@@ -9,80 +9,30 @@ pub mod opcodes {
     pub const PUT_OPCODE: u8 = 11;
 }
 
-pub mod structs {
-    pub struct Ldr;
-    pub struct Str;
-    pub struct Mov;
-    pub struct Put;
-}
-
 use opcodes::*;
-use structs::*;
-
-fn assemble_ldr_str(opcode: u8, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-    vec![
-        opcode << 2,
-        (operands[0][1..].parse::<u8>().expect("Invalid register id") << 4) +
-        operands[1][1..].parse::<u8>().expect("Invalid value"),
-    ]
-}
-
-impl Executable for Ldr {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.common_registers[reg1] = computer.memory[computer.common_registers[reg2] as usize] as u16;
-    }
 
-    fn mnemonic(&self) -> String { String::from("ldr") }
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "ldr", opcode: LDR_OPCODE, format: Format::RegReg, kind: Kind::Load, privileged: false, dataflow: dataflow::COPY, description: "load the byte at address rB into rA", execute: ldr },
+    InstructionSpec { mnemonic: "str", opcode: STR_OPCODE, format: Format::RegReg, kind: Kind::Store, privileged: false, dataflow: dataflow::READ_AB, description: "store the low byte of rA at address rB", execute: str },
+    InstructionSpec { mnemonic: "mov", opcode: MOV_OPCODE, format: Format::RegReg, kind: Kind::Alu, privileged: false, dataflow: dataflow::COPY, description: "rA = rB", execute: mov },
+    InstructionSpec { mnemonic: "put", opcode: PUT_OPCODE, format: Format::RegValue, kind: Kind::Alu, privileged: false, dataflow: dataflow::WRITE_A, description: "rA = value", execute: put },
+];
 
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_ldr_str(LDR_OPCODE, operands, operand_types)
-    }
+fn ldr(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.read_byte(computer.common_registers[ops.reg2])? as u16;
+    Ok(())
 }
 
-impl Executable for Str {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.memory[computer.common_registers[reg2] as usize] = computer.common_registers[reg1] as u8;
-    }
-
-    fn mnemonic(&self) -> String { String::from("str") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_ldr_str(STR_OPCODE, operands, operand_types)
-    }
+fn str(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.write_byte(computer.common_registers[ops.reg2], computer.common_registers[ops.reg1] as u8)
 }
 
-impl Executable for Mov {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let (reg1, reg2) = get_next_reg_reg_operands(computer);
-        computer.common_registers[reg1] = computer.common_registers[reg2];
-    }
-
-    fn mnemonic(&self) -> String { String::from("mov") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![
-            MOV_OPCODE << 2,
-            (operands[0][1..].parse::<u8>().expect("Invalid register id") << 4) +
-            operands[1][1..].parse::<u8>().expect("Invalid register id"),
-        ]
-    }
+fn mov(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg2];
+    Ok(())
 }
 
-impl Executable for Put {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let reg = get_next_reg_operand(computer);
-        computer.common_registers[reg] = computer.next_byte() as u16;
-    }
-
-    fn mnemonic(&self) -> String { String::from("put") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![
-            PUT_OPCODE << 2,
-            operands[0][1..].parse::<u8>().expect("Invalid register id") << 4,
-            operands[1].parse::<u8>().expect("Invalid value"),
-        ]
-    }
+fn put(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = ops.value as u16;
+    Ok(())
 }
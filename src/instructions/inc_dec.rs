@@ -1,49 +1,25 @@
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
 
-use super::{Executable, operands::{get_next_reg_operand, OperandType}};
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
 
 pub mod opcodes {
     pub const INC_OPCODE: u8 = 6;
     pub const DEC_OPCODE: u8 = 7;
 }
 
-pub mod structs {
-    pub struct Inc;
-    pub struct Dec;
-}
-
 use opcodes::*;
-use structs::*;
-
-fn assemble_inc_dec(opcode: u8, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-    vec![
-        opcode << 2,
-        operands[0][1..].parse::<u8>().expect("Invalid register id") << 4,
-    ]
-}
 
-impl Executable for Inc {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let reg = get_next_reg_operand(computer);
-        computer.common_registers[reg] += 1;
-    }
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "inc", opcode: INC_OPCODE, format: Format::Reg, kind: Kind::Alu, privileged: false, dataflow: dataflow::UPDATE_A, description: "rA += 1", execute: inc },
+    InstructionSpec { mnemonic: "dec", opcode: DEC_OPCODE, format: Format::Reg, kind: Kind::Alu, privileged: false, dataflow: dataflow::UPDATE_A, description: "rA -= 1", execute: dec },
+];
 
-    fn mnemonic(&self) -> String { String::from("inc") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_inc_dec(INC_OPCODE, operands, operand_types)
-    }
+fn inc(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg1].wrapping_add(1);
+    Ok(())
 }
 
-impl Executable for Dec {
-    fn execute(&self, computer: &mut Computer, first_byte: u8) {
-        let reg = get_next_reg_operand(computer);
-        computer.common_registers[reg] -= 1;
-    }
-
-    fn mnemonic(&self) -> String { String::from("dec") }
-
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        assemble_inc_dec(DEC_OPCODE, operands, operand_types)
-    }
+fn dec(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.common_registers[ops.reg1].wrapping_sub(1);
+    Ok(())
 }
@@ -0,0 +1,94 @@
+use crate::computer::{Computer, Fault, Mode};
+
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
+
+pub mod opcodes {
+    pub const EI_OPCODE: u8 = 21;
+    pub const DI_OPCODE: u8 = 22;
+    pub const IRET_OPCODE: u8 = 23;
+    pub const IVEC_OPCODE: u8 = 24;
+    pub const RDSR_OPCODE: u8 = 27;
+    pub const WRSR_OPCODE: u8 = 28;
+    pub const SYS_OPCODE: u8 = 29;
+}
+
+// System registers accessible with `rdsr` and `wrsr`
+pub mod system_registers {
+    pub const SR_SAVED_IP: u8 = 0;
+    pub const SR_TRAP_CAUSE: u8 = 1;
+    pub const SR_FAULT_ADDRESS: u8 = 2;
+    pub const SR_TRAP_TABLE: u8 = 3; // 0 disables traps
+    pub const SR_INTERRUPT_VECTOR: u8 = 4;
+    pub const SR_PAGE_TABLE: u8 = 5; // only with an MMU, 0 disables translation
+    pub const SR_SAVED_MODE: u8 = 6; // 0 - supervisor, 1 - user
+}
+
+use opcodes::*;
+use system_registers::*;
+
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "ei", opcode: EI_OPCODE, format: Format::None, kind: Kind::System, privileged: true, dataflow: dataflow::NONE, description: "enable interrupts", execute: ei },
+    InstructionSpec { mnemonic: "di", opcode: DI_OPCODE, format: Format::None, kind: Kind::System, privileged: true, dataflow: dataflow::NONE, description: "disable interrupts", execute: di },
+    InstructionSpec { mnemonic: "iret", opcode: IRET_OPCODE, format: Format::None, kind: Kind::System, privileged: true, dataflow: dataflow::NONE, description: "return from an interrupt or trap handler", execute: iret },
+    InstructionSpec { mnemonic: "ivec", opcode: IVEC_OPCODE, format: Format::Reg, kind: Kind::System, privileged: true, dataflow: dataflow::READ_A, description: "set the interrupt vector to rA", execute: ivec },
+    InstructionSpec { mnemonic: "rdsr", opcode: RDSR_OPCODE, format: Format::RegValue, kind: Kind::System, privileged: true, dataflow: dataflow::WRITE_A, description: "read system register value into rA", execute: rdsr },
+    InstructionSpec { mnemonic: "wrsr", opcode: WRSR_OPCODE, format: Format::ValueReg, kind: Kind::System, privileged: true, dataflow: dataflow::READ_A, description: "write rA to system register value", execute: wrsr },
+    InstructionSpec { mnemonic: "sys", opcode: SYS_OPCODE, format: Format::Value, kind: Kind::System, privileged: false, dataflow: dataflow::NONE, description: "call syscall value", execute: sys },
+];
+
+fn ei(computer: &mut Computer, _ops: Operands) -> Result<(), Fault> {
+    computer.interrupts_enabled = true;
+    Ok(())
+}
+
+fn di(computer: &mut Computer, _ops: Operands) -> Result<(), Fault> {
+    computer.interrupts_enabled = false;
+    Ok(())
+}
+
+fn iret(computer: &mut Computer, _ops: Operands) -> Result<(), Fault> {
+    computer.ip = computer.saved_ip;
+    computer.flags = computer.saved_flags;
+    computer.interrupts_enabled = computer.saved_interrupts_enabled;
+    computer.set_mode(computer.saved_mode);
+    Ok(())
+}
+
+fn ivec(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.interrupt_vector = computer.common_registers[ops.reg1];
+    Ok(())
+}
+
+fn rdsr(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = match ops.value {
+        SR_SAVED_IP => computer.saved_ip,
+        SR_TRAP_CAUSE => computer.trap_cause as u16,
+        SR_FAULT_ADDRESS => computer.fault_address,
+        SR_TRAP_TABLE => computer.trap_table.unwrap_or(0),
+        SR_INTERRUPT_VECTOR => computer.interrupt_vector,
+        SR_PAGE_TABLE if computer.has_mmu => computer.page_table.unwrap_or(0),
+        SR_SAVED_MODE => (computer.saved_mode == Mode::User) as u16,
+        sr => return Err(Fault::InvalidSystemRegister(sr)),
+    };
+    Ok(())
+}
+
+fn wrsr(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    let value = computer.common_registers[ops.reg1];
+
+    match ops.value {
+        SR_SAVED_IP => computer.saved_ip = value,
+        SR_TRAP_CAUSE => computer.trap_cause = value as u8,
+        SR_FAULT_ADDRESS => computer.fault_address = value,
+        SR_TRAP_TABLE => computer.trap_table = if value == 0 { None } else { Some(value) },
+        SR_INTERRUPT_VECTOR => computer.interrupt_vector = value,
+        SR_PAGE_TABLE if computer.has_mmu => computer.set_page_table(if value == 0 { None } else { Some(value) }),
+        SR_SAVED_MODE => computer.saved_mode = if value == 0 { Mode::Supervisor } else { Mode::User },
+        sr => return Err(Fault::InvalidSystemRegister(sr)),
+    }
+    Ok(())
+}
+
+fn sys(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.syscall(ops.value)
+}
@@ -0,0 +1,24 @@
+use crate::computer::{Computer, Fault};
+
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
+
+pub mod opcodes {
+    pub const IN_OPCODE: u8 = 25;
+    pub const OUT_OPCODE: u8 = 26;
+}
+
+use opcodes::*;
+
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "in", opcode: IN_OPCODE, format: Format::RegValue, kind: Kind::Io, privileged: false, dataflow: dataflow::WRITE_A, description: "read a byte from port value into rA", execute: input },
+    InstructionSpec { mnemonic: "out", opcode: OUT_OPCODE, format: Format::ValueReg, kind: Kind::Io, privileged: false, dataflow: dataflow::READ_A, description: "write the low byte of rA to port value", execute: output },
+];
+
+fn input(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.common_registers[ops.reg1] = computer.read_port(ops.value)? as u16;
+    Ok(())
+}
+
+fn output(computer: &mut Computer, ops: Operands) -> Result<(), Fault> {
+    computer.write_port(ops.value, computer.common_registers[ops.reg1] as u8)
+}
@@ -1,35 +1,22 @@
-use crate::computer::Computer;
+use crate::computer::{Computer, Fault};
 
-use super::{Executable, operands::OperandType};
+use super::{dataflow, InstructionSpec, Kind, operands::{Format, Operands}};
 
 pub mod opcodes {
     pub const NOP_OPCODE: u8 = 0;
     pub const HALT_OPCODE: u8 = 1;
 }
 
-pub mod structs {
-    pub struct Nop;
-    pub struct Halt;
-}
-
 use opcodes::*;
-use structs::*;
 
-impl Executable for Nop {
-    fn execute(&self, _computer: &mut Computer, _first_byte: u8) {}
-    fn mnemonic(&self) -> String { String::from("nop") }
+pub static SPECS: &[InstructionSpec] = &[
+    InstructionSpec { mnemonic: "nop", opcode: NOP_OPCODE, format: Format::None, kind: Kind::Alu, privileged: false, dataflow: dataflow::NONE, description: "do nothing", execute: nop },
+    InstructionSpec { mnemonic: "halt", opcode: HALT_OPCODE, format: Format::None, kind: Kind::System, privileged: true, dataflow: dataflow::NONE, description: "stop the computer", execute: halt },
+];
 
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![NOP_OPCODE << 2]
-    }
-    
-}
+fn nop(_computer: &mut Computer, _ops: Operands) -> Result<(), Fault> { Ok(()) }
 
-impl Executable for Halt {
-    fn execute(&self, computer: &mut Computer, _first_byte: u8) { computer.should_halt = true }
-    fn mnemonic(&self) -> String { String::from("halt") }
-    fn assemble(&self, operands: & Vec<String>, operand_types: &Vec<OperandType>) -> Vec<u8> {
-        vec![HALT_OPCODE << 2]
-    }
-    
+fn halt(computer: &mut Computer, _ops: Operands) -> Result<(), Fault> {
+    computer.should_halt = true;
+    Ok(())
 }
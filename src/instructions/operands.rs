@@ -1,22 +1,119 @@
-use crate::computer::Computer;
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum OperandType {
+    Register,
+    Value,
+}
 
-pub fn get_next_reg_reg_operands(computer: &mut Computer) -> (usize, usize) {
-    let regs_byte = computer.next_byte();
-    let reg1 = ((regs_byte & 0b11110000) >> 4) as usize;
-    let reg2 = (regs_byte & 0b00001111) as usize;
+// Fields the format doesn't use are left at 0
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Operands {
+    pub reg1: usize,
+    pub reg2: usize,
+    pub value: u8,
+}
 
-    (reg1, reg2)
+/// How operands are written in source and encoded after the opcode byte.
+///
+/// Registers take a nibble each in the byte following the opcode (the first one in the high nibble),
+/// a value takes a byte of its own after them.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    None,     // op
+    Reg,      // op rA
+    RegReg,   // op rA rB
+    RegValue, // op rA value
+    ValueReg, // op value rA, encoded the same way as RegValue
+    Value,    // op value
 }
 
-pub fn get_next_reg_operand(computer: &mut Computer) -> usize {
-    let regs_byte = computer.next_byte();
-    let reg = ((regs_byte & 0b11110000) >> 4) as usize;
+impl Format {
+    pub fn size(self) -> usize {
+        match self {
+            Format::None => 1,
+            Format::Reg | Format::RegReg | Format::Value => 2,
+            Format::RegValue | Format::ValueReg => 3,
+        }
+    }
 
-    reg
-}
+    pub fn operand_types(self) -> &'static [OperandType] {
+        use OperandType::*;
 
-#[derive(PartialEq, Debug)]
-pub enum OperandType {
-    Register,
-    Value,
+        match self {
+            Format::None => &[],
+            Format::Reg => &[Register],
+            Format::RegReg => &[Register, Register],
+            Format::RegValue => &[Register, Value],
+            Format::ValueReg => &[Value, Register],
+            Format::Value => &[Value],
+        }
+    }
+
+    pub fn register_count(self) -> usize {
+        self.operand_types().iter().filter(|t| **t == OperandType::Register).count()
+    }
+
+    pub fn syntax(self) -> &'static str {
+        match self {
+            Format::None => "",
+            Format::Reg => "rA",
+            Format::RegReg => "rA rB",
+            Format::RegValue => "rA value",
+            Format::ValueReg => "value rA",
+            Format::Value => "value",
+        }
+    }
+
+    pub fn parse(self, operands: &[String]) -> Result<Operands, String> {
+        let reg = |s: &String| s[1..].parse::<usize>().ok().filter(|r| *r < 16).ok_or_else(|| format!("invalid register {}", s));
+        let value = |s: &String| s.parse::<u8>().map_err(|_| format!("invalid value {}, expected 0 to 255", s));
+
+        Ok(match self {
+            Format::None => Operands::default(),
+            Format::Reg => Operands { reg1: reg(&operands[0])?, ..Default::default() },
+            Format::RegReg => Operands { reg1: reg(&operands[0])?, reg2: reg(&operands[1])?, ..Default::default() },
+            Format::RegValue => Operands { reg1: reg(&operands[0])?, value: value(&operands[1])?, ..Default::default() },
+            Format::ValueReg => Operands { reg1: reg(&operands[1])?, value: value(&operands[0])?, ..Default::default() },
+            Format::Value => Operands { value: value(&operands[0])?, ..Default::default() },
+        })
+    }
+
+    pub fn encode(self, opcode: u8, ops: &Operands) -> Vec<u8> {
+        let first = opcode << 2;
+        let regs = ((ops.reg1 as u8) << 4) | ops.reg2 as u8;
+
+        match self {
+            Format::None => vec![first],
+            Format::Reg | Format::RegReg => vec![first, regs],
+            Format::RegValue | Format::ValueReg => vec![first, regs, ops.value],
+            Format::Value => vec![first, ops.value],
+        }
+    }
+
+    // `bytes` starts at the opcode and holds at least `size()` bytes
+    pub fn decode(self, bytes: &[u8]) -> Operands {
+        let reg1 = (bytes.get(1).copied().unwrap_or(0) >> 4) as usize;
+
+        match self {
+            Format::None => Operands::default(),
+            Format::Reg => Operands { reg1, ..Default::default() },
+            Format::RegReg => Operands { reg1, reg2: (bytes[1] & 0b00001111) as usize, ..Default::default() },
+            Format::RegValue | Format::ValueReg => Operands { reg1, value: bytes[2], ..Default::default() },
+            Format::Value => Operands { value: bytes[1], ..Default::default() },
+        }
+    }
+
+    pub fn format_operands(self, ops: &Operands) -> Vec<String> {
+        let r1 = format!("r{}", ops.reg1);
+        let r2 = format!("r{}", ops.reg2);
+        let v = ops.value.to_string();
+
+        match self {
+            Format::None => Vec::new(),
+            Format::Reg => vec![r1],
+            Format::RegReg => vec![r1, r2],
+            Format::RegValue => vec![r1, v],
+            Format::ValueReg => vec![v, r1],
+            Format::Value => vec![v],
+        }
+    }
 }
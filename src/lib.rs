@@ -0,0 +1,12 @@
+pub mod bus;
+pub mod devices;
+pub mod computer;
+pub mod config;
+pub mod instructions;
+pub mod assembler;
+pub mod disassembler;
+pub mod debugger;
+pub mod hex;
+
+#[cfg(test)]
+mod test_util;
@@ -1,36 +1,548 @@
-use computer::Computer;
-use assembler::Assembler;
+use rustrone::bus::MemoryBus;
+use rustrone::computer::{Computer, Engine, Permissions, Segment};
+use rustrone::computer::{BranchPredictor, Cache, Snapshot};
+use rustrone::config::{CacheConfig, MachineConfig, PipelineConfig, PredictorConfig, RegionConfig};
+use rustrone::assembler::{Assembler, Symbols};
+use rustrone::devices::{ConsoleInput, ConsoleOutput, HostInput, Ram, SharedBuffer};
+use rustrone::debugger::{Connection, DapServer, Debugger, GdbStub, Repl, StdioConnection};
+use rustrone::disassembler::disassemble;
+use rustrone::instructions::{all_instructions, group_of};
 
-use std::env;
+use std::{env, fs, io, process};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::Instant;
 
-mod computer;
-mod instructions;
-mod assembler;
+const CONSOLE_OUTPUT_PORT: u8 = 0;
+const CONSOLE_INPUT_PORT: u8 = 4;
+
+// With --mmio-console the console takes the top 16 bytes of the first 256 instead
+const CONSOLE_OUTPUT_BASE: u16 = 0xF0;
+const CONSOLE_INPUT_BASE: u16 = 0xF4;
+const CONSOLE_RESERVED_END: u16 = 0x100;
 
 fn print_usage() {
-    println!("Usage:\trustrone [file]");
-    println!("\tfile - file with source code");
+    println!("Usage:\trustrone [-q] [--engine name] [machine options] [snapshot options] [file]");
+    println!("\trustrone disasm [file]");
+    println!("\trustrone isa");
+    println!("\trustrone bench [machine options] [file]");
+    println!("\trustrone debug [machine options] [file]");
+    println!("\trustrone gdb [--listen PORT] [machine options] [file]");
+    println!("\trustrone dap [machine options] - serve the Debug Adapter Protocol, the client picks the program");
+    println!("\tfile   - file with source code, or a raw binary for disasm");
+    println!("\t-q     - don't dump registers after every instruction");
+    println!("\t--stats - report instructions, cycles and CPI to stderr at the end");
+    println!("\t--pipeline-diagram - show what every pipeline stage holds in every cycle");
+    println!("\t--engine - interpreter, cached (default) or block");
+    println!("\t--listen PORT - serve gdb on a local TCP port instead of stdin and stdout");
+    println!("\t--mmio-console - map the console to addresses 240-246 instead of ports 0-6");
+    println!("Machine options, overriding the config file:");
+    println!("\t--config FILE  - TOML or JSON (.json) machine config");
+    println!("\t--memory BYTES - RAM size, at most 65536");
+    println!("\t--registers N  - register count, at most 16");
+    println!("\t--reset-vector ADDR");
+    println!("\t--sp ADDR      - initial value of the last register");
+    println!("\t--groups LIST  - comma separated instruction groups, see isa");
+    println!("\t--protect START:SIZE:PERMS - restrict memory, like rw- for data or --- for a guard, can be repeated");
+    println!("\t--pipeline     - count cycles with a 5-stage pipeline model");
+    println!("\t--no-forwarding - the pipeline stalls until results are written back");
+    println!("\t--icache SIZE:LINE:WAYS - add an instruction cache, like 64:8:2");
+    println!("\t--dcache SIZE:LINE:WAYS - add a data cache");
+    println!("\t--predictor KIND - predict branches with not-taken, one-bit, two-bit or gshare");
+    println!("\t--mispredict-penalty CYCLES - charge for mispredictions instead of taken branches");
+    println!("Loading:");
+    println!("\t--load-at ADDR - where to load the program, 0 by default");
+    println!("\t--perms PERMS  - program permissions, like r-x for read-only or --x for execute-only");
+    println!("\t--segment ADDR:FILE[:PERMS] - load another file, can be repeated");
+    println!("\t--entry ADDR   - where to start, the reset vector by default");
+    println!("Snapshots:");
+    println!("\t--load-state FILE - continue from a saved state, the program file is optional then");
+    println!("\t--save-state FILE - save the state when the program stops");
+    println!("\t--stop-after N    - stop after N instructions");
+}
+
+#[derive(Clone)]
+struct SegmentOption {
+    addr: u16,
+    file: String,
+    permissions: Option<Permissions>,
+}
+
+impl SegmentOption {
+    // ADDR:FILE[:PERMS]
+    fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid segment {}", s);
+
+        let mut parts = s.splitn(2, ':');
+        let addr = parts.next().and_then(|a| a.parse().ok()).ok_or_else(invalid)?;
+        let rest = parts.next().ok_or_else(invalid)?;
+
+        let (file, permissions) = match rest.rsplit_once(':') {
+            Some((file, perms)) => (file, Some(perms.parse()?)),
+            None => (rest, None),
+        };
+
+        Ok(Self { addr, file: file.to_string(), permissions })
+    }
+
+    fn load(&self) -> Result<(Segment, Symbols), String> {
+        let (bytes, symbols) = load_bytes(&self.file, self.addr)?;
+        Ok((Segment { addr: self.addr, bytes, permissions: self.permissions }, symbols))
+    }
+}
+
+struct Options {
+    command: String,
+    files: Vec<String>,
+    quiet: bool,
+    stats: bool,
+    pipeline_diagram: bool,
+    engine: Engine,
+    config: MachineConfig,
+    // Where the program goes, it comes first among the segments
+    load_at: u16,
+    permissions: Option<Permissions>,
+    segments: Vec<SegmentOption>,
+    entry: Option<u16>,
+    load_state: Option<String>,
+    save_state: Option<String>,
+    stop_after: Option<u64>,
+    listen: Option<u16>,
+    mmio_console: bool,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} needs a number", flag))
+}
+
+// START:SIZE:PERMS
+fn parse_region(s: &str) -> Result<RegionConfig, String> {
+    let invalid = || format!("invalid region {}", s);
+
+    let parts: Vec<&str> = s.split(':').collect();
+    let [start, size, perms] = parts[..] else {
+        return Err(invalid());
+    };
+
+    Ok(RegionConfig {
+        start: start.parse().map_err(|_| invalid())?,
+        size: size.parse().map_err(|_| invalid())?,
+        permissions: perms.parse()?,
+    })
+}
+
+// SIZE:LINE:WAYS, the config file sets the rest
+fn parse_cache(s: &str) -> Result<CacheConfig, String> {
+    let invalid = || format!("invalid cache {}", s);
+
+    let parts: Vec<&str> = s.split(':').collect();
+    let [size, line_size, associativity] = parts[..] else {
+        return Err(invalid());
+    };
+
+    Ok(CacheConfig {
+        size: size.parse().map_err(|_| invalid())?,
+        line_size: line_size.parse().map_err(|_| invalid())?,
+        associativity: associativity.parse().map_err(|_| invalid())?,
+        ..Default::default()
+    })
+}
+
+fn apply_machine_option(config: &mut MachineConfig, flag: &str, value: &str) -> Result<(), String> {
+    match flag {
+        "--memory" => config.memory_size = parse_number(flag, value)?,
+        "--registers" => config.registers = parse_number(flag, value)?,
+        "--reset-vector" => config.reset_vector = parse_number(flag, value)?,
+        "--sp" => config.initial_sp = Some(parse_number(flag, value)?),
+        "--groups" => config.groups = value.split(',').map(String::from).collect(),
+        "--protect" => config.regions.push(parse_region(value)?),
+        "--pipeline" => { config.pipeline.get_or_insert_with(PipelineConfig::default); },
+        "--no-forwarding" => config.pipeline.get_or_insert_with(PipelineConfig::default).forwarding = false,
+        "--icache" => config.icache = Some(parse_cache(value)?),
+        "--dcache" => config.dcache = Some(parse_cache(value)?),
+        "--predictor" => config.predictor.get_or_insert_with(PredictorConfig::default).kind = value.parse()?,
+        "--mispredict-penalty" => config.predictor.get_or_insert_with(PredictorConfig::default).penalty = Some(parse_number(flag, value)?),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut quiet = false;
+    let mut stats = false;
+    let mut pipeline_diagram = false;
+    let mut engine = Engine::default();
+    let mut listen = None;
+    let mut mmio_console = false;
+
+    // Applied on top of the config file, wherever it appears
+    let mut config_file = None;
+    let mut machine_options = Vec::new();
+
+    let mut load_at = 0;
+    let mut permissions = None;
+    let mut segments = Vec::new();
+    let mut entry = None;
+
+    let mut load_state = None;
+    let mut save_state = None;
+    let mut stop_after = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" => quiet = true,
+            "--stats" => stats = true,
+            "--pipeline-diagram" => pipeline_diagram = true,
+            "--mmio-console" => mmio_console = true,
+            "--pipeline" | "--no-forwarding" => machine_options.push((arg, String::new())),
+            "--engine" => engine = args.next().ok_or("--engine needs a name")?.parse()?,
+            "--listen" => listen = Some(parse_number(&arg, &args.next().unwrap_or_default())?),
+            "--config" => config_file = Some(args.next().ok_or("--config needs a file")?),
+            "--memory" | "--registers" | "--reset-vector" | "--sp" | "--groups" | "--protect" | "--icache" | "--dcache"
+            | "--predictor" | "--mispredict-penalty" => {
+                let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                machine_options.push((arg, value));
+            },
+            "--load-at" => load_at = parse_number(&arg, &args.next().unwrap_or_default())?,
+            "--perms" => permissions = Some(args.next().ok_or("--perms needs permissions")?.parse()?),
+            "--segment" => segments.push(SegmentOption::parse(&args.next().ok_or("--segment needs a segment")?)?),
+            "--entry" => entry = Some(parse_number(&arg, &args.next().unwrap_or_default())?),
+            "--load-state" => load_state = Some(args.next().ok_or("--load-state needs a file")?),
+            "--save-state" => save_state = Some(args.next().ok_or("--save-state needs a file")?),
+            "--stop-after" => stop_after = Some(parse_number(&arg, &args.next().unwrap_or_default())?),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut config = match config_file {
+        Some(path) => MachineConfig::load(&path).map_err(|e| e.to_string())?,
+        None => MachineConfig::default(),
+    };
+    for (flag, value) in &machine_options {
+        apply_machine_option(&mut config, flag, value)?;
+    }
+    config.validate().map_err(|e| e.to_string())?;
+
+    let command = match positional.first().map(String::as_str) {
+        Some("disasm") | Some("isa") | Some("bench") | Some("debug") | Some("gdb") | Some("dap") => positional.remove(0),
+        _ => String::from("run"),
+    };
+
+    let expected_args = match command.as_str() {
+        "isa" | "dap" => 0..=0,
+        "disasm" => 1..=1,
+        _ if load_state.is_some() => 0..=1,
+        _ => 1..=1,
+    };
+    if !expected_args.contains(&positional.len()) {
+        return Err(String::from("invalid arguments"));
+    }
+
+    if let Some(file) = positional.first() {
+        segments.insert(0, SegmentOption { addr: load_at, file: file.clone(), permissions });
+    }
+
+    if pipeline_diagram && config.pipeline.is_none() {
+        return Err(String::from("--pipeline-diagram needs a pipeline"));
+    }
+
+    Ok(Options {
+        command, files: positional, quiet, stats, pipeline_diagram, engine, config, load_at, permissions, segments, entry,
+        load_state, save_state, stop_after, listen, mmio_console,
+    })
+}
+
+// Where the program's console and syscalls read and write
+enum HostIo {
+    Stdio,
+    Stderr, // stdin and stdout carry gdb's protocol, there is no input
+    Captured(SharedBuffer), // forwarded to a DAP client, there is no input
+}
+
+impl HostIo {
+    fn output(&self) -> Box<dyn Write> {
+        match self {
+            HostIo::Stdio => Box::new(io::stdout()),
+            HostIo::Stderr => Box::new(io::stderr()),
+            HostIo::Captured(buffer) => Box::new(buffer.clone()),
+        }
+    }
+
+    fn input(&self) -> Box<dyn Read> {
+        match self {
+            HostIo::Stdio => Box::new(HostInput),
+            _ => Box::new(io::empty()),
+        }
+    }
+
+    fn console(&self) -> (ConsoleOutput, ConsoleInput) {
+        match self {
+            HostIo::Stdio => (ConsoleOutput::stdout(), ConsoleInput::stdin()),
+            _ => (ConsoleOutput::new(self.output()), ConsoleInput::from_bytes(Vec::new())),
+        }
+    }
+}
+
+fn host_io(opts: &Options) -> HostIo {
+    if opts.command == "gdb" && opts.listen.is_none() {
+        HostIo::Stderr
+    } else {
+        HostIo::Stdio
+    }
+}
+
+fn attach_devices(comp: &mut Computer, (output, input): (ConsoleOutput, ConsoleInput)) {
+    comp.ports.claim(CONSOLE_OUTPUT_PORT, Box::new(output)).unwrap();
+    comp.ports.claim(CONSOLE_INPUT_PORT, Box::new(input)).unwrap();
+}
+
+// RAM is split around the console, the reserved addresses aren't backed by anything else
+fn memory_mapped_console_bus(memory_size: usize, (output, input): (ConsoleOutput, ConsoleInput)) -> MemoryBus {
+    let mut bus = MemoryBus::new();
+    bus.map(0, Box::new(Ram::new(memory_size.min(CONSOLE_OUTPUT_BASE as usize)))).unwrap();
+    bus.map(CONSOLE_OUTPUT_BASE, Box::new(output)).unwrap();
+    bus.map(CONSOLE_INPUT_BASE, Box::new(input)).unwrap();
+    if memory_size > CONSOLE_RESERVED_END as usize {
+        bus.map(CONSOLE_RESERVED_END, Box::new(Ram::new(memory_size - CONSOLE_RESERVED_END as usize))).unwrap();
+    }
+
+    bus
+}
+
+// Anything that isn't a .s source file is treated as machine code, without symbols
+fn load_bytes(fname: &str, origin: u16) -> Result<(Vec<u8>, Symbols), String> {
+    if fname.ends_with(".s") {
+        Assembler::with_origin(origin).assemble_with_symbols(fname)
+    } else {
+        let bytes = fs::read(fname).map_err(|e| format!("couldn't read {}: {}", fname, e))?;
+        Ok((bytes, Symbols::default()))
+    }
 }
 
-fn main() -> Result<(), &'static str> {
-    let args: Vec<String> = env::args().collect();
+fn disasm(fname: &str) -> Result<(), String> {
+    for instr in disassemble(&load_bytes(fname, 0)?.0, 0) {
+        println!("{}", instr);
+    }
+
+    Ok(())
+}
+
+fn isa() -> Result<(), String> {
+    println!("{:<7} {:<16} {:<5} {:<14} description", "opcode", "syntax", "size", "group");
+
+    let mut specs: Vec<_> = all_instructions().collect();
+    specs.sort_by_key(|spec| spec.opcode);
+
+    for spec in specs {
+        let syntax = format!("{} {}", spec.mnemonic, spec.format.syntax());
+        let privileged = if spec.privileged { ", supervisor only" } else { "" };
+        println!("{:<7} {:<16} {:<5} {:<14} {}{}", spec.opcode, syntax, spec.size(), group_of(spec).name, spec.description, privileged);
+    }
+
+    Ok(())
+}
+
+fn new_computer(opts: &Options, engine: Engine) -> Result<(Computer, Symbols), String> {
+    build_computer(opts, engine, &opts.segments, &host_io(opts))
+}
+
+fn build_computer(
+    opts: &Options, engine: Engine, segments: &[SegmentOption], host_io: &HostIo,
+) -> Result<(Computer, Symbols), String> {
+    let console = host_io.console();
+    let mut comp = if opts.mmio_console {
+        let mut comp = Computer::with_bus(memory_mapped_console_bus(opts.config.memory_size, console));
+        comp.configure(&opts.config).map_err(|e| e.to_string())?;
+        comp
+    } else {
+        let mut comp = Computer::with_config(&opts.config).map_err(|e| e.to_string())?;
+        attach_devices(&mut comp, console);
+        comp
+    };
+    comp.engine = engine;
+    comp.syscall_output = host_io.output();
+    comp.syscall_input = host_io.input();
+
+    let mut symbols = Symbols::default();
+    for option in segments {
+        let (segment, segment_symbols) = option.load()?;
+        comp.load_segment(&segment)
+            .map_err(|e| format!("couldn't load {}: {}", option.file, e))?;
+        symbols.extend(segment_symbols);
+    }
+    // The program is only loaded for its symbols then
+    if let Some(path) = &opts.load_state {
+        let snapshot = Snapshot::load(path).map_err(|e| e.to_string())?;
+        comp.restore_snapshot(&snapshot).map_err(|e| e.to_string())?;
+    }
+    if let Some(entry) = opts.entry {
+        comp.ip = entry;
+    }
+
+    Ok((comp, symbols))
+}
+
+fn bench(opts: &Options) -> Result<(), String> {
+    for engine in Engine::ALL {
+        let (mut comp, _) = new_computer(opts, engine)?;
+
+        let start = Instant::now();
+        while comp.tick().map_err(|e| format!("{} at ip {}", e, comp.ip))? {}
+        let secs = start.elapsed().as_secs_f64();
+
+        println!(
+            "{:<12} {:>12} instructions {:>8.3} s {:>14.0} instructions/s",
+            engine.name(), comp.instructions_retired, secs, comp.instructions_retired as f64 / secs,
+        );
+    }
+
+    Ok(())
+}
+
+fn print_stats(comp: &Computer) {
+    let cpi = comp.cycles as f64 / comp.instructions_retired.max(1) as f64;
+
+    eprintln!("instructions: {}", comp.instructions_retired);
+    eprintln!("cycles:       {}", comp.cycles);
+    eprintln!("CPI:          {:.3}", cpi);
+
+    if let Some(pipeline) = &comp.pipeline {
+        eprintln!("data hazard stalls:    {}", pipeline.data_stalls);
+        eprintln!("control hazard stalls: {}", pipeline.control_stalls);
+    }
+
+    for (name, cache) in [("icache", &comp.icache), ("dcache", &comp.dcache)] {
+        if let Some(cache) = cache {
+            print_cache_stats(name, cache);
+        }
+    }
 
-    if args.len() != 2 {
-        print_usage();
-        return Err("invalid arguments");
+    if let Some(predictor) = &comp.predictor {
+        print_predictor_stats(predictor);
     }
+}
 
-    let fname = args[1].clone();
+fn print_predictor_stats(predictor: &BranchPredictor) {
+    let total = predictor.total;
+    eprintln!("branches: {}, mispredicted: {}, accuracy {:.1}%", total.executed, total.mispredicted, total.accuracy() * 100.0);
 
-    let mut comp = Computer::new(256);
-    
-    let a = Assembler::new();
-    let prg = a.assemble(&fname);
-    comp.load_program(prg);
+    for (addr, stats) in &predictor.by_address {
+        eprintln!("  {:>5}: {:>8} executed, {:>8} mispredicted, accuracy {:.1}%", addr, stats.executed, stats.mispredicted, stats.accuracy() * 100.0);
+    }
+}
 
-    comp.dump_memory(0..20);
-    println!();
-    while comp.tick() { println!(); }
+fn print_cache_stats(name: &str, cache: &Cache) {
+    eprintln!(
+        "{}: {} accesses, {} hits, {} misses, {} writebacks, hit rate {:.1}%",
+        name, cache.accesses(), cache.hits, cache.misses, cache.writebacks, cache.hit_rate() * 100.0,
+    );
+}
+
+fn run(opts: &Options) -> Result<(), String> {
+    let quiet = opts.quiet;
+    let (mut comp, _) = new_computer(opts, opts.engine)?;
+    if let Some(pipeline) = comp.pipeline.as_mut().filter(|_| opts.pipeline_diagram) {
+        pipeline.show_diagram(Box::new(io::stdout()));
+    }
+
+    if !quiet {
+        comp.dump_memory(0..20);
+        println!();
+    }
+
+    // A snapshot may have been saved after the program halted
+    let stop_at = opts.stop_after.map(|n| comp.instructions_retired + n);
+    let mut running = !comp.should_halt && stop_at != Some(comp.instructions_retired);
+    while running {
+        running = comp.tick().map_err(|e| format!("{} at ip {}", e, comp.ip))?;
+        if !quiet {
+            comp.dump();
+        }
+        if !running || stop_at.is_some_and(|n| comp.instructions_retired >= n) { break; }
+        if !quiet {
+            println!();
+        }
+    }
+
+    if let Some(pipeline) = &mut comp.pipeline {
+        pipeline.finish();
+    }
+    if opts.stats {
+        print_stats(&comp);
+    }
+    if let Some(path) = &opts.save_state {
+        comp.snapshot().save(path).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(code) = comp.exit_code.filter(|_| comp.should_halt) {
+        process::exit(code as i32);
+    }
 
     Ok(())
 }
+
+fn debug(opts: &Options) -> Result<(), String> {
+    let (comp, symbols) = new_computer(opts, opts.engine)?;
+    let mut repl = Repl::new(Debugger::new(comp, symbols), io::stdout());
+    // The program's console input comes from stdin too, between commands
+    repl.run(io::BufReader::new(HostInput)).map_err(|e| e.to_string())
+}
+
+fn serve_gdb(debugger: Debugger, conn: impl Connection) -> Result<(), String> {
+    GdbStub::new(debugger, conn).run().map_err(|e| e.to_string())
+}
+
+fn gdb(opts: &Options) -> Result<(), String> {
+    let (comp, symbols) = new_computer(opts, opts.engine)?;
+    let debugger = Debugger::new(comp, symbols);
+
+    match opts.listen {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("couldn't listen on port {}: {}", port, e))?;
+            eprintln!("waiting for gdb on port {}", port);
+            let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+            serve_gdb(debugger, stream)
+        },
+        None => serve_gdb(debugger, StdioConnection),
+    }
+}
+
+// The program's console and syscall output is forwarded to the client, it has no input
+fn dap(opts: Options) -> Result<(), String> {
+    let output = SharedBuffer::new();
+    let host_io = HostIo::Captured(output.clone());
+
+    let launcher = Box::new(move |program: &str| {
+        let mut segments = vec![SegmentOption { addr: opts.load_at, file: program.to_string(), permissions: opts.permissions }];
+        segments.extend(opts.segments.iter().cloned());
+
+        let (comp, symbols) = build_computer(&opts, opts.engine, &segments, &host_io)?;
+        Ok(Debugger::new(comp, symbols))
+    });
+
+    let mut server = DapServer::new(launcher, Some(output), io::stdout());
+    server.run(io::BufReader::new(HostInput)).map_err(|e| e.to_string())
+}
+
+fn main() -> Result<(), String> {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(e) => {
+            print_usage();
+            return Err(e);
+        },
+    };
+
+    match opts.command.as_str() {
+        "disasm" => disasm(&opts.files[0]),
+        "isa" => isa(),
+        "bench" => bench(&opts),
+        "debug" => debug(&opts),
+        "gdb" => gdb(&opts),
+        "dap" => dap(opts),
+        _ => run(&opts),
+    }
+}
@@ -0,0 +1,47 @@
+// Helpers shared by the unit tests
+
+use std::path::PathBuf;
+use std::{env, fs, io, process};
+
+use crate::assembler::{Assembler, Symbols};
+use crate::computer::{Computer, Engine};
+use crate::devices::{ConsoleInput, ConsoleOutput, SharedBuffer};
+
+pub const CONSOLE_OUTPUT_PORT: u8 = 0;
+pub const CONSOLE_INPUT_PORT: u8 = 4;
+
+// A temporary source file, `name` keeps tests running at the same time apart
+pub fn source_file(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rustrone-{}-{}.s", process::id(), name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+pub fn assemble(name: &str, source: &str) -> (Vec<u8>, Symbols) {
+    let path = source_file(name, source);
+    let assembled = Assembler::new().assemble_with_symbols(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+    assembled.unwrap()
+}
+
+// One of the programs next to Cargo.toml
+pub fn sample(name: &str) -> Vec<u8> {
+    let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name);
+    Assembler::new().assemble(&path).unwrap()
+}
+
+// The default machine with the console on its usual ports, without input,
+// the console and syscall output is captured
+pub fn machine(program: &[u8], engine: Engine) -> (Computer, SharedBuffer) {
+    let mut comp = Computer::new(256);
+    comp.engine = engine;
+
+    let (output, buffer) = ConsoleOutput::buffered();
+    comp.ports.claim(CONSOLE_OUTPUT_PORT, Box::new(output)).unwrap();
+    comp.ports.claim(CONSOLE_INPUT_PORT, Box::new(ConsoleInput::from_bytes(Vec::new()))).unwrap();
+    comp.syscall_output = Box::new(buffer.clone());
+    comp.syscall_input = Box::new(io::empty());
+
+    comp.load_program(program.to_vec()).unwrap();
+    (comp, buffer)
+}